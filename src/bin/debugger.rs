@@ -0,0 +1,413 @@
+use std::io::{ self, BufRead, Write };
+use tomasulo_emulator::cpu::{ TomasuloCpu, SingleCycleCpu, ScoreboardCpu, PipelinedCpu, Cpu, Instruction, Retired };
+use tomasulo_emulator::parser::{ Parser, InitMode };
+use tomasulo_emulator::trace::Trace;
+
+/// 断点类型
+enum Breakpoint {
+    /// 序号为 pc 的指令发射（单周期 CPU 为执行）
+    Pc(usize),
+    /// 某种指令发射，例如 DIV
+    Inst(String),
+    /// 寄存器被写入
+    RegWrite(usize),
+    /// 访存指令访问该地址
+    MemAddr(u32)
+}
+
+impl Breakpoint {
+    fn parse(args: &[&str]) -> Option<Self> {
+        match args {
+            ["pc", pc] => pc.parse().ok().map(Breakpoint::Pc),
+            ["inst", name] => Some(Breakpoint::Inst(name.to_uppercase())),
+            ["reg", reg] => reg.trim_start_matches(['R', 'r']).parse().ok().map(Breakpoint::RegWrite),
+            ["mem", addr] => parse_addr(addr).map(Breakpoint::MemAddr),
+            _ => None
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Breakpoint::Pc(pc) => format!("pc {}", pc),
+            Breakpoint::Inst(name) => format!("inst {}", name),
+            Breakpoint::RegWrite(reg) => format!("reg R{}", reg),
+            Breakpoint::MemAddr(addr) => format!("mem {:#x}", addr)
+        }
+    }
+}
+
+fn parse_addr(s: &str) -> Option<u32> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    }else {
+        s.parse().ok()
+    }
+}
+
+/// 调试器所需的 CPU 操作，对两种 CPU 统一封装
+trait Target {
+    /// 运行一步，返回本步发射的指令序号范围以及提交的指令
+    fn step(&mut self) -> (std::ops::Range<usize>, Vec<Retired>);
+    fn done(&self) -> bool;
+    fn cycles(&self) -> usize;
+    fn regs(&self) -> [i32; 32];
+    fn read_memory(&self, addr: u32) -> i32;
+    /// 打印 CPU 内部结构，例如 rs、rob
+    fn print(&self, what: &str) -> bool;
+}
+
+impl<'a> Target for TomasuloCpu<'a> {
+    fn step(&mut self) -> (std::ops::Range<usize>, Vec<Retired>) {
+        let events = TomasuloCpu::step(self);
+        let issued = match (events.issued.first(), events.issued.last()) {
+            (Some(first), Some(last)) => first.0..last.0 + 1,
+            _ => self.pc()..self.pc()
+        };
+        (issued, events.committed)
+    }
+
+    fn done(&self) -> bool { TomasuloCpu::done(self) }
+
+    fn cycles(&self) -> usize { TomasuloCpu::cycles(self) }
+
+    fn regs(&self) -> [i32; 32] { *TomasuloCpu::regs(self) }
+
+    fn read_memory(&self, addr: u32) -> i32 { TomasuloCpu::read_memory(self, addr) }
+
+    fn print(&self, what: &str) -> bool {
+        match what {
+            "rs" => {
+                println!(
+                    "{:<4}{:<10}{:<6}{:<6}{:<16}{:>8}{:>8}{:>22}{:>22}{:>22}{:>8}",
+                    "#", "type", "busy", "exec", "inst", "Vj", "Vk", "Qj", "Qk", "dest", "addr"
+                );
+                for (i, rs) in self.reservation_stations().iter().enumerate() {
+                    println!("{:<4}{}", i, rs);
+                }
+            },
+            "rob" => {
+                println!(
+                    "{:<4}{:>22}  {:<6}{:<6}{:>6}  {:<16}{:>6}{:>12}{:>8}",
+                    "#", "tag", "busy", "ready", "pc", "inst", "dest", "value", "addr"
+                );
+                for (i, rob) in self.reorder_buffer().iter().enumerate() {
+                    println!("{:<4}{}", i, rob);
+                }
+            },
+            "regstat" => match self.physical_registers() {
+                Some(prf) => for reg in 0..32 {
+                    let phys = prf.mapping(reg);
+                    println!("R{:<4}P{:<6}{:<6}{}", reg, phys, prf.ready(phys), prf.value(phys));
+                },
+                None => for (i, stat) in self.register_status().iter().enumerate() {
+                    println!("R{:<4}{}", i, stat);
+                }
+            },
+            "units" => {
+                for (i, unit) in self.exec_units().iter().enumerate() {
+                    println!("{:<4}{}", i, unit);
+                }
+            },
+            "sb" => {
+                println!("{:<4}{:<6}{:<10}{:>12}{:>10}", "#", "pc", "addr", "value", "done");
+                for (i, entry) in self.store_buffer().iter().enumerate() {
+                    println!("{:<4}{}", i, entry);
+                }
+            },
+            "queue" => {
+                for (i, inst) in self.pending_insts().take(16).enumerate() {
+                    println!("{:<6}{}", self.pc() + i, inst);
+                }
+            },
+            _ => return false
+        }
+        true
+    }
+}
+
+impl<'a> Target for ScoreboardCpu<'a> {
+    fn step(&mut self) -> (std::ops::Range<usize>, Vec<Retired>) {
+        let events = ScoreboardCpu::step(self);
+        let issued = match events.issued.first() {
+            Some((pc, _)) => *pc..*pc + 1,
+            None => self.pc()..self.pc()
+        };
+        (issued, events.committed)
+    }
+
+    fn done(&self) -> bool { ScoreboardCpu::done(self) }
+
+    fn cycles(&self) -> usize { ScoreboardCpu::cycles(self) }
+
+    fn regs(&self) -> [i32; 32] { *ScoreboardCpu::regs(self) }
+
+    fn read_memory(&self, addr: u32) -> i32 { ScoreboardCpu::read_memory(self, addr) }
+
+    fn print(&self, what: &str) -> bool {
+        match what {
+            "units" => {
+                println!(
+                    "{:<4}{:<10}{:<14}{:<16}{:>4}{:>4}{:>4}{:>4}{:>4}{:>7}{:>7}{:>5}",
+                    "#", "type", "stage", "inst", "Fi", "Fj", "Fk", "Qj", "Qk", "Rj", "Rk", "left"
+                );
+                for (i, unit) in self.units().iter().enumerate() {
+                    println!("{:<4}{}", i, unit);
+                }
+            },
+            "regstat" => {
+                for (i, unit) in self.result_status().iter().enumerate() {
+                    match unit {
+                        Some(unit) => println!("R{:<4}unit {}", i, unit),
+                        None => println!("R{:<4}-", i)
+                    }
+                }
+            },
+            "queue" => {
+                for (i, inst) in self.instruction_queue().iter().take(16).enumerate() {
+                    println!("{:<6}{}", self.pc() + i, inst);
+                }
+            },
+            _ => return false
+        }
+        true
+    }
+}
+
+impl<'a> Target for PipelinedCpu<'a> {
+    fn step(&mut self) -> (std::ops::Range<usize>, Vec<Retired>) {
+        let events = PipelinedCpu::step(self);
+        let issued = match events.issued.first() {
+            Some((pc, _)) => *pc..*pc + 1,
+            None => self.pc()..self.pc()
+        };
+        (issued, events.committed)
+    }
+
+    fn done(&self) -> bool { PipelinedCpu::done(self) }
+
+    fn cycles(&self) -> usize { PipelinedCpu::cycles(self) }
+
+    fn regs(&self) -> [i32; 32] { *PipelinedCpu::regs(self) }
+
+    fn read_memory(&self, addr: u32) -> i32 { PipelinedCpu::read_memory(self, addr) }
+
+    fn print(&self, what: &str) -> bool {
+        match what {
+            "stages" => {
+                for (name, stage) in self.stages() {
+                    match stage {
+                        Some(slot) => println!("{:<14}{}", name, slot),
+                        None => println!("{:<14}-", name)
+                    }
+                }
+            },
+            "queue" => {
+                for (i, inst) in self.instruction_queue().iter().take(16).enumerate() {
+                    println!("{:<6}{}", self.pc() + i, inst);
+                }
+            },
+            _ => return false
+        }
+        true
+    }
+}
+
+impl<'a> Target for SingleCycleCpu<'a> {
+    fn step(&mut self) -> (std::ops::Range<usize>, Vec<Retired>) {
+        let pc = self.pc();
+        let retired = SingleCycleCpu::step(self);
+        (pc..self.pc(), retired.into_iter().collect())
+    }
+
+    fn done(&self) -> bool { SingleCycleCpu::done(self) }
+
+    fn cycles(&self) -> usize { SingleCycleCpu::cycles(self) }
+
+    fn regs(&self) -> [i32; 32] { *SingleCycleCpu::regs(self) }
+
+    fn read_memory(&self, addr: u32) -> i32 { SingleCycleCpu::read_memory(self, addr) }
+
+    fn print(&self, _what: &str) -> bool {
+        false
+    }
+}
+
+struct Debugger {
+    program: Vec<Instruction>,
+    breakpoints: Vec<Breakpoint>
+}
+
+impl Debugger {
+    /// 运行一步并检查断点，返回本步提交的指令数以及命中的断点描述
+    fn step<T: Target>(&self, cpu: &mut T) -> (usize, Option<String>) {
+        let (issued, retired) = cpu.step();
+        for item in retired.iter() {
+            match item.reg_write {
+                Some((dest, val)) => println!("  commit [{}] {}  R{} <- {}", item.pc, item.inst, dest, val),
+                None => println!("  commit [{}] {}", item.pc, item.inst)
+            }
+        }
+        for bp in self.breakpoints.iter() {
+            let hit = match bp {
+                Breakpoint::Pc(pc) => issued.contains(pc),
+                Breakpoint::Inst(name) => issued.clone().any(|pc| self.program.get(pc).map(|inst| inst.name()) == Some(name.as_str())),
+                Breakpoint::RegWrite(reg) => retired.iter().any(|item| matches!(item.reg_write, Some((dest, _)) if dest == *reg)),
+                Breakpoint::MemAddr(addr) => retired.iter().any(|item| item.address == Some(*addr))
+            };
+            if hit {
+                return (retired.len(), Some(bp.describe()))
+            }
+        }
+        (retired.len(), None)
+    }
+
+    /// 一直运行直到 stop 返回 true、命中断点或者运行结束，
+    /// stop 的第二个参数为本步提交的指令数
+    fn run_until<T, F>(&self, cpu: &mut T, mut stop: F)
+        where T: Target, F: FnMut(&T, usize) -> bool
+    {
+        while !cpu.done() {
+            let (committed, bp) = self.step(cpu);
+            if let Some(bp) = bp {
+                println!("breakpoint hit: {} (cycle {})", bp, cpu.cycles());
+                return
+            }
+            if stop(cpu, committed) { break; }
+        }
+        if cpu.done() {
+            println!("program finished at cycle {}", cpu.cycles());
+        }
+    }
+
+    fn repl<T: Target>(&mut self, cpu: &mut T) {
+        let stdin = io::stdin();
+        loop {
+            print!("(cycle {}) > ", cpu.cycles());
+            io::stdout().flush().unwrap();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap() == 0 { break; }
+            let args: Vec<&str> = line.split_whitespace().collect();
+            match args.as_slice() {
+                [] => {},
+                ["s" | "step", rest @ ..] => {
+                    let mut left: usize = rest.first().and_then(|n| n.parse().ok()).unwrap_or(1).max(1);
+                    self.run_until(cpu, |_, _| { left -= 1; left == 0 });
+                },
+                ["c" | "commit", rest @ ..] => {
+                    let mut left: usize = rest.first().and_then(|n| n.parse().ok()).unwrap_or(1).max(1);
+                    self.run_until(cpu, |_, committed| { left = left.saturating_sub(committed); left == 0 });
+                },
+                ["r" | "run", cycle] => {
+                    let cycle: usize = cycle.parse().unwrap_or(0);
+                    self.run_until(cpu, |cpu, _| cpu.cycles() >= cycle);
+                },
+                ["g" | "continue"] => self.run_until(cpu, |_, _| false),
+                ["b" | "break", rest @ ..] => {
+                    match Breakpoint::parse(rest) {
+                        Some(bp) => {
+                            println!("breakpoint {}: {}", self.breakpoints.len(), bp.describe());
+                            self.breakpoints.push(bp);
+                        },
+                        None => println!("usage: b pc <n> | b inst <NAME> | b reg <Rn> | b mem <addr>")
+                    }
+                },
+                ["bl"] => {
+                    for (i, bp) in self.breakpoints.iter().enumerate() {
+                        println!("{}: {}", i, bp.describe());
+                    }
+                },
+                ["d" | "delete", id] => {
+                    match id.parse::<usize>() {
+                        Ok(id) if id < self.breakpoints.len() => { self.breakpoints.remove(id); },
+                        _ => println!("no such breakpoint")
+                    }
+                },
+                ["p" | "print", "regs"] => {
+                    for (i, reg) in cpu.regs().iter().enumerate() {
+                        print!("R{:<3}{:>12}{}", i, reg, if i % 4 == 3 { "\n" } else { "   " });
+                    }
+                },
+                ["p" | "print", "mem", addr, rest @ ..] => {
+                    let len: u32 = rest.first().and_then(|n| n.parse().ok()).unwrap_or(1);
+                    match parse_addr(addr) {
+                        // 到地址空间末尾为止，不回绕到 0
+                        Some(addr) => for addr in (0..len).map_while(|i| addr.checked_add(i.checked_mul(4)?)) {
+                            println!("{:#010x}: {}", addr, cpu.read_memory(addr));
+                        },
+                        None => println!("invalid address")
+                    }
+                },
+                ["p" | "print", what] => {
+                    if !cpu.print(what) {
+                        println!("nothing to print for `{}`", what);
+                    }
+                },
+                ["h" | "help"] => print_help(),
+                ["q" | "quit"] => break,
+                _ => println!("unknown command, type `h` for help")
+            }
+        }
+    }
+}
+
+fn print_help() {
+    println!("s [n]           step n cycles (single cycle cpu: n instructions)");
+    println!("c [n]           step until n instructions commit");
+    println!("r <cycle>       run to cycle");
+    println!("g               continue until breakpoint or end");
+    println!("b pc <n>        break when instruction n issues");
+    println!("b inst <NAME>   break when an instruction like DIV issues");
+    println!("b reg <Rn>      break when register n is written");
+    println!("b mem <addr>    break when memory address is accessed");
+    println!("bl / d <id>     list / delete breakpoints");
+    println!("p rs|rob|regstat|units|sb|stages|queue|regs");
+    println!("p mem <addr> [len]");
+    println!("q               quit");
+}
+
+/// 用法: debugger [tomasulo|scoreboard|pipeline|single] [inst file] [data file] [--init random[:seed]|zero|file]
+fn main() {
+    let mut args: Vec<String> = std::env::args().collect();
+    let init = InitMode::from_args(&args).unwrap();
+    if let Some(pos) = args.iter().position(|arg| arg == "--init") {
+        args.drain(pos..args.len().min(pos + 2));
+    }
+    let kind = args.get(1).map(|s| s.as_str()).unwrap_or("tomasulo");
+    let inst_file = args.get(2).map(|s| s.as_str()).unwrap_or("inst.txt");
+    let data_file = args.get(3).map(|s| s.as_str()).unwrap_or("data.txt");
+
+    let parser = Parser::new();
+    let mut trace = Trace::new("traces/debugger.txt");
+    let program = parser.read_program(inst_file).expect("Fail to read instruction");
+    let mut debugger = Debugger{ program: program.clone(), breakpoints: vec![] };
+    match kind {
+        "scoreboard" => {
+            let mut cpu = ScoreboardCpu::new(&mut trace);
+            program.into_iter().for_each(|inst| cpu.add_inst(inst));
+            parser.read_data(&mut cpu, data_file).expect("Fail to read data");
+            parser.init(&mut cpu, &init).expect("Fail to initialize registers");
+            debugger.repl(&mut cpu);
+        },
+        "pipeline" => {
+            let mut cpu = PipelinedCpu::new(&mut trace);
+            program.into_iter().for_each(|inst| cpu.add_inst(inst));
+            parser.read_data(&mut cpu, data_file).expect("Fail to read data");
+            parser.init(&mut cpu, &init).expect("Fail to initialize registers");
+            debugger.repl(&mut cpu);
+        },
+        "single" => {
+            let mut cpu = SingleCycleCpu::new(&mut trace);
+            program.into_iter().for_each(|inst| cpu.add_inst(inst));
+            parser.read_data(&mut cpu, data_file).expect("Fail to read data");
+            parser.init(&mut cpu, &init).expect("Fail to initialize registers");
+            debugger.repl(&mut cpu);
+        },
+        _ => {
+            let mut cpu = TomasuloCpu::new(&mut trace);
+            program.into_iter().for_each(|inst| cpu.add_inst(inst));
+            parser.read_data(&mut cpu, data_file).expect("Fail to read data");
+            parser.init(&mut cpu, &init).expect("Fail to initialize registers");
+            debugger.repl(&mut cpu);
+        }
+    }
+}
@@ -7,7 +7,7 @@ fn main() {
     let mut inst_file = File::create("inst.txt").unwrap();
     let mut data_file = File::create("data.txt").unwrap();
 
-    let inst_type = vec!["ADD", "SUB", "MUL", "DIV", "LD", "JUMP"];
+    let inst_type = ["ADD", "SUB", "MUL", "DIV", "LD", "JUMP"];
     for _ in 0..5000 {
         let idx = rng.gen_range(0..5);
         match inst_type[idx] {
@@ -0,0 +1,22 @@
+use tomasulo_emulator::cpu::{ PipelinedCpu, Cpu, Forwarding };
+use tomasulo_emulator::parser::{ Parser, InitMode };
+use tomasulo_emulator::trace::Trace;
+
+/// 用法: pipeline [--forwarding full|none|ex_mem,mem_wb,regfile] [--dump state.txt|state.json] [--init random[:seed]|zero|file]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut trace = Trace::new("traces/pipeline.txt");
+    let mut cpu = PipelinedCpu::new(&mut trace);
+    if let Some(pos) = args.iter().position(|arg| arg == "--forwarding") {
+        let forwarding: Forwarding = args.get(pos + 1).expect("missing forwarding").parse().unwrap();
+        cpu.set_forwarding(forwarding);
+    }
+    let parser = Parser::new();
+    parser.read_inst(&mut cpu, "inst.txt").expect("Fail to read instruction");
+    parser.read_data(&mut cpu, "data.txt").expect("Fail to read data");
+    parser.init(&mut cpu, &InitMode::from_args(&args).unwrap()).expect("Fail to initialize registers");
+    let stats = cpu.run();
+    println!("{}", stats);
+    cpu.state().dump(&args).expect("Fail to write state");
+    std::fs::write("traces/pipeline_stats.json", stats.to_json()).expect("Fail to write stats");
+}
@@ -0,0 +1,18 @@
+use tomasulo_emulator::cpu::{ ScoreboardCpu, Cpu };
+use tomasulo_emulator::parser::{ Parser, InitMode };
+use tomasulo_emulator::trace::Trace;
+
+/// 用法: scoreboard [--dump state.txt|state.json] [--init random[:seed]|zero|file]
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let mut trace = Trace::new("traces/scoreboard.txt");
+    let mut cpu = ScoreboardCpu::new(&mut trace);
+    let parser = Parser::new();
+    parser.read_inst(&mut cpu, "inst.txt").expect("Fail to read instruction");
+    parser.read_data(&mut cpu, "data.txt").expect("Fail to read data");
+    parser.init(&mut cpu, &InitMode::from_args(&args).unwrap()).expect("Fail to initialize registers");
+    let stats = cpu.run();
+    println!("{}", stats);
+    cpu.state().dump(&args).expect("Fail to write state");
+    std::fs::write("traces/scoreboard_stats.json", stats.to_json()).expect("Fail to write stats");
+}
@@ -1,21 +1,58 @@
-use rand::Rng;
-use tomasulo_emulator::cpu::{ SingleCycleCpu, Cpu };
-use tomasulo_emulator::parser::Parser;
+use tomasulo_emulator::cpu::{ SingleCycleCpu, Cpu, Endian, Misaligned, MemoryMap, device::standard_devices };
+use tomasulo_emulator::parser::{ Parser, InitMode };
 use tomasulo_emulator::trace::Trace;
-use tomasulo_emulator::cpu::{ Instruction, Operand };
 
 
 
+/// 用法: single_cycle [--endian little|big] [--misaligned trap|split|allow]
+///                     [--memory-map standard|name:base:size:perm,...] [--mmio] [--dump state.txt|state.json]
+///                     [--checkpoint-at cycle file] [--restore file] [--init random[:seed]|zero|file]
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
     let mut trace = Trace::new("traces/single_cycle.txt");
     let mut cpu = SingleCycleCpu::new(&mut trace);
-    let mut rng = rand::thread_rng();
-    for i in 0..32 {
-        let rand_val = rng.gen_range(0..1000);
-        cpu.set_regs(i, rand_val);
+    if let Some(pos) = args.iter().position(|arg| arg == "--endian") {
+        let endian: Endian = args.get(pos + 1).expect("missing endian").parse().unwrap();
+        cpu.set_endian(endian);
     }
-    let parser = Parser::new();
-    parser.read_inst(&mut cpu, "inst.txt").expect("Fail to read instruction");
-    parser.read_data(&mut cpu, "data.txt").expect("Fail to read data");
-    cpu.run();
+    if let Some(pos) = args.iter().position(|arg| arg == "--misaligned") {
+        let misaligned: Misaligned = args.get(pos + 1).expect("missing misaligned policy").parse().unwrap();
+        cpu.set_misaligned(misaligned);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--memory-map") {
+        let map: MemoryMap = args.get(pos + 1).expect("missing memory map").parse().unwrap();
+        cpu.set_memory_map(Some(map));
+    }
+    if args.iter().any(|arg| arg == "--mmio") {
+        for (base, device) in standard_devices() {
+            cpu.attach_device(base, device).unwrap();
+        }
+    }
+    match args.iter().position(|arg| arg == "--restore") {
+        // 检查点中已经包含剩余的指令与内存，其中的配置会覆盖命令行参数
+        Some(pos) => {
+            let filename = args.get(pos + 1).expect("missing checkpoint file");
+            let checkpoint = std::fs::read_to_string(filename).expect("Fail to read checkpoint");
+            cpu.restore(&checkpoint).expect("Fail to restore checkpoint");
+        },
+        None => {
+            let parser = Parser::new();
+            parser.read_inst(&mut cpu, "inst.txt").expect("Fail to read instruction");
+            parser.read_data(&mut cpu, "data.txt").expect("Fail to read data");
+            parser.init(&mut cpu, &InitMode::from_args(&args).unwrap()).expect("Fail to initialize registers");
+        }
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--checkpoint-at") {
+        let cycle: usize = args.get(pos + 1).expect("missing checkpoint cycle").parse().unwrap();
+        let filename = args.get(pos + 2).expect("missing checkpoint file");
+        while !cpu.done() && cpu.cycles() < cycle {
+            cpu.step();
+        }
+        std::fs::write(filename, cpu.checkpoint()).expect("Fail to write checkpoint");
+    }
+    let stats = cpu.run();
+    print!("{}", cpu.output());
+    println!("{}", stats);
+    cpu.state().dump(&args).expect("Fail to write state");
+    std::fs::write("traces/single_cycle_stats.json", stats.to_json()).expect("Fail to write stats");
 }
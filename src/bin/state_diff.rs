@@ -0,0 +1,23 @@
+use tomasulo_emulator::parser::Parser;
+
+/// 用法: state_diff expected actual
+/// 比较两个状态文件（文本或 JSON），有不同时逐条打印并以 1 退出
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, expected, actual] = args.as_slice() else {
+        eprintln!("usage: state_diff expected actual");
+        std::process::exit(2);
+    };
+    let parser = Parser::new();
+    let expected = parser.read_state(expected).expect("Fail to read expected state");
+    let actual = parser.read_state(actual).expect("Fail to read actual state");
+    let diffs = actual.diff(&expected);
+    for diff in &diffs {
+        println!("{}", diff);
+    }
+    if !diffs.is_empty() {
+        println!("{} differences", diffs.len());
+        std::process::exit(1);
+    }
+    println!("states match");
+}
@@ -1,21 +1,149 @@
-use tomasulo_emulator::{cpu::{ TomasuloCpu, Cpu, Operand, Instruction }, trace::Trace};
-use tomasulo_emulator::parser::Parser;
-use rand::Rng;
+use tomasulo_emulator::{cpu::{ TomasuloCpu, SingleCycleCpu, Sampling, Cpu, Operand, Instruction, Width, SelectPolicy, CdbArbitration, StageLatency, FrontEnd, RenameMode, Endian, Misaligned, MemoryMap, device::standard_devices, CacheConfig, CacheHierarchy, WritePolicy, cache::DEFAULT_MEMORY_LATENCY }, trace::Trace};
+use tomasulo_emulator::parser::{ Parser, InitMode };
+
+/// 用法: tomasulo [--select oldest|position|random[:seed]|critical] [--cdb n] [--cdb-arbitration oldest|unit|round-robin]
+///                 [--stage-latency issue_to_dispatch,wakeup_to_select,writeback_to_commit]
+///                 [--front-end fetch_width,queue_depth,decode_delay,redirect_penalty]
+///                 [--rename rob|prf:n|classic]
+///                 [--l1 size,assoc,line_size,lru|fifo|random[:seed],hit_latency] [--l2 ...] [--mem-latency n] [--mshr n]
+///                 [--write-policy wb|wt,wa|nwa] [--store-buffer n]
+///                 [--endian little|big] [--misaligned trap|split|allow]
+///                 [--memory-map standard|name:base:size:perm,...] [--mmio] [--dump state.txt|state.json]
+///                 [--checkpoint-at cycle file] [--restore file] [--sample fast_forward,warmup,detail[,period]]
+///                 [--init random[:seed]|zero|file]
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(pos) = args.iter().position(|arg| arg == "--sample") {
+        let sampling: Sampling = args.get(pos + 1).expect("missing sampling config").parse().unwrap();
+        sample(&args, sampling);
+        return
+    }
     let mut trace = Trace::new("traces/tomasulo.txt");
     let mut cpu = TomasuloCpu::new(&mut trace);
-    let mut rng = rand::thread_rng();
-    for i in 0..32 {
-        let rand_val = rng.gen_range(0..1000);
-        cpu.set_regs(i, rand_val);
+    configure(&mut cpu, &args);
+    match args.iter().position(|arg| arg == "--restore") {
+        // 检查点中已经包含剩余的指令与内存，其中的配置会覆盖命令行参数
+        Some(pos) => {
+            let filename = args.get(pos + 1).expect("missing checkpoint file");
+            let checkpoint = std::fs::read_to_string(filename).expect("Fail to read checkpoint");
+            cpu.restore(&checkpoint).expect("Fail to restore checkpoint");
+        },
+        None => {
+            let parser = Parser::new();
+            parser.read_inst(&mut cpu, "inst.txt").expect("Fail to read instruction");
+            parser.read_data(&mut cpu, "data.txt").expect("Fail to read data");
+            parser.init(&mut cpu, &InitMode::from_args(&args).unwrap()).expect("Fail to initialize registers");
+        }
     }
-    let parser = Parser::new();
-    parser.read_inst(&mut cpu, "inst.txt").expect("Fail to read instruction");
-    parser.read_data(&mut cpu, "data.txt").expect("Fail to read data");
-    cpu.run();
+    if let Some(pos) = args.iter().position(|arg| arg == "--checkpoint-at") {
+        let cycle: usize = args.get(pos + 1).expect("missing checkpoint cycle").parse().unwrap();
+        let filename = args.get(pos + 2).expect("missing checkpoint file");
+        while !cpu.done() && cpu.cycles() < cycle {
+            cpu.step();
+        }
+        std::fs::write(filename, cpu.checkpoint()).expect("Fail to write checkpoint");
+    }
+    let stats = cpu.run();
+    print!("{}", cpu.output());
+    println!("{}", stats);
+    cpu.state().dump(&args).expect("Fail to write state");
+    std::fs::write("traces/tomasulo_stats.json", stats.to_json()).expect("Fail to write stats");
     // jump_test();
 }
 
+/// 按命令行参数配置 CPU
+fn configure(cpu: &mut TomasuloCpu, args: &[String]) {
+    if let Some(pos) = args.iter().position(|arg| arg == "--select") {
+        let policy: SelectPolicy = args.get(pos + 1).expect("missing select policy").parse().unwrap();
+        cpu.set_select_policy(policy);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--stage-latency") {
+        let latency: StageLatency = args.get(pos + 1).expect("missing stage latency").parse().unwrap();
+        cpu.set_stage_latency(latency);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--front-end") {
+        let front_end: FrontEnd = args.get(pos + 1).expect("missing front end").parse().unwrap();
+        cpu.set_front_end(Some(front_end));
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--rename") {
+        let mode: RenameMode = args.get(pos + 1).expect("missing rename mode").parse().unwrap();
+        cpu.set_rename_mode(mode);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--cdb") {
+        let count: usize = args.get(pos + 1).expect("missing CDB count").parse().unwrap();
+        cpu.set_cdb_count(Some(count));
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--cdb-arbitration") {
+        let arbitration: CdbArbitration = args.get(pos + 1).expect("missing CDB arbitration").parse().unwrap();
+        cpu.set_cdb_arbitration(arbitration);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--l1") {
+        let l1: CacheConfig = args.get(pos + 1).expect("missing L1 config").parse().unwrap();
+        let l2: Option<CacheConfig> = args.iter().position(|arg| arg == "--l2")
+            .map(|pos| args.get(pos + 1).expect("missing L2 config").parse().unwrap());
+        let memory_latency: usize = args.iter().position(|arg| arg == "--mem-latency")
+            .map(|pos| args.get(pos + 1).expect("missing memory latency").parse().unwrap())
+            .unwrap_or(DEFAULT_MEMORY_LATENCY);
+        let mut cache = CacheHierarchy::new(l1, l2, memory_latency).expect("Invalid cache config");
+        if let Some(pos) = args.iter().position(|arg| arg == "--mshr") {
+            let count: usize = args.get(pos + 1).expect("missing MSHR count").parse().unwrap();
+            cache.set_mshrs(Some(count)).expect("Invalid MSHR count");
+        }
+        if let Some(pos) = args.iter().position(|arg| arg == "--write-policy") {
+            let policy: WritePolicy = args.get(pos + 1).expect("missing write policy").parse().unwrap();
+            cache.set_write_policy(policy);
+        }
+        cpu.set_cache(Some(cache));
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--store-buffer") {
+        let size: usize = args.get(pos + 1).expect("missing store buffer size").parse().unwrap();
+        cpu.set_store_buffer_size(size);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--endian") {
+        let endian: Endian = args.get(pos + 1).expect("missing endian").parse().unwrap();
+        cpu.set_endian(endian);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--misaligned") {
+        let misaligned: Misaligned = args.get(pos + 1).expect("missing misaligned policy").parse().unwrap();
+        cpu.set_misaligned(misaligned);
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--memory-map") {
+        let map: MemoryMap = args.get(pos + 1).expect("missing memory map").parse().unwrap();
+        cpu.set_memory_map(Some(map));
+    }
+    if args.iter().any(|arg| arg == "--mmio") {
+        for (base, device) in standard_devices() {
+            cpu.attach_device(base, device).unwrap();
+        }
+    }
+}
+
+/// 单周期 CPU 快进，TomasuloCpu 详细模拟采样区间
+fn sample(args: &[String], sampling: Sampling) {
+    let mut functional_trace = Trace::new("traces/single_cycle.txt");
+    let mut functional = SingleCycleCpu::new(&mut functional_trace);
+    // 快进与详细模拟的访存语义需要一致
+    if let Some(pos) = args.iter().position(|arg| arg == "--endian") {
+        functional.set_endian(args.get(pos + 1).expect("missing endian").parse().unwrap());
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--misaligned") {
+        functional.set_misaligned(args.get(pos + 1).expect("missing misaligned policy").parse().unwrap());
+    }
+    if let Some(pos) = args.iter().position(|arg| arg == "--memory-map") {
+        functional.set_memory_map(Some(args.get(pos + 1).expect("missing memory map").parse().unwrap()));
+    }
+    let parser = Parser::new();
+    parser.read_inst(&mut functional, "inst.txt").expect("Fail to read instruction");
+    parser.read_data(&mut functional, "data.txt").expect("Fail to read data");
+    parser.init(&mut functional, &InitMode::from_args(args).unwrap()).expect("Fail to initialize registers");
+    let mut trace = Trace::new("traces/tomasulo.txt");
+    let stats = sampling.run(&mut functional, &mut trace, |cpu| configure(cpu, args));
+    println!("{}", stats);
+    functional.state().dump(args).expect("Fail to write state");
+    std::fs::write("traces/tomasulo_sampling.json", stats.to_json()).expect("Fail to write stats");
+}
+
+#[allow(dead_code)]
 fn fp_test() {
     let mut trace = Trace::new("traces/tomasulo.txt");
     let mut cpu = TomasuloCpu::new(&mut trace);
@@ -33,18 +161,20 @@ fn fp_test() {
     cpu.run();
 }
 
+#[allow(dead_code)]
 fn load_test() {
     let mut trace = Trace::new("traces/tomasulo.txt");
     let mut cpu = TomasuloCpu::new(&mut trace);
     cpu.write_memory(0x0, 1);
     cpu.write_memory(0x4, 2);
     cpu.write_memory(0x8, 4);
-    cpu.add_inst(Instruction::Ld(1, 0, 0x0));
-    cpu.add_inst(Instruction::Ld(2, 0, 0x4));
-    cpu.add_inst(Instruction::Ld(3, 0, 0x8));
+    cpu.add_inst(Instruction::Ld(1, 0, 0x0, Width::Word));
+    cpu.add_inst(Instruction::Ld(2, 0, 0x4, Width::Word));
+    cpu.add_inst(Instruction::Ld(3, 0, 0x8, Width::Word));
     cpu.run();
 }
 
+#[allow(dead_code)]
 fn jump_test() {
     let mut trace = Trace::new("traces/tomasulo.txt");
     let mut cpu = TomasuloCpu::new(&mut trace);
@@ -55,4 +185,4 @@ fn jump_test() {
     cpu.add_inst(Instruction::Jump(2, 3));
     cpu.add_inst(Instruction::Jump(3, 4));
     cpu.run();
-}
\ No newline at end of file
+}
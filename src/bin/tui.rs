@@ -0,0 +1,160 @@
+use std::io::{ self, Write };
+use std::time::Duration;
+
+use crossterm::{ cursor, execute, queue, terminal };
+use crossterm::event::{ self, Event, KeyCode, KeyEventKind };
+use crossterm::style::Print;
+use tomasulo_emulator::cpu::TomasuloCpu;
+use tomasulo_emulator::parser::{ Parser, InitMode };
+use tomasulo_emulator::trace::Trace;
+
+/// 将若干行包装成带标题的面板，每行补齐或截断到 width 个字符
+fn panel(title: &str, lines: Vec<String>, width: usize, height: usize) -> Vec<String> {
+    let mut out = vec![fit(&format!("== {} ", title), width, '=')];
+    for line in lines.into_iter().take(height) {
+        out.push(fit(&line, width, ' '));
+    }
+    while out.len() < height + 1 {
+        out.push(fit("", width, ' '));
+    }
+    out
+}
+
+fn fit(s: &str, width: usize, pad: char) -> String {
+    let mut out: String = s.chars().take(width).collect();
+    while out.chars().count() < width {
+        out.push(pad);
+    }
+    out
+}
+
+/// 将多个面板横向拼接
+fn hstack(panels: Vec<Vec<String>>) -> Vec<String> {
+    let height = panels.iter().map(|panel| panel.len()).max().unwrap_or(0);
+    (0..height).map(|i| {
+        panels.iter().map(|panel| panel.get(i).cloned().unwrap_or_default()).collect::<Vec<_>>().join(" | ")
+    }).collect()
+}
+
+fn render(cpu: &TomasuloCpu, running: bool, delay: u64, width: usize) -> Vec<String> {
+    let stats = cpu.stats();
+    let mut screen = vec![
+        fit(&format!(
+            "Tomasulo  cycle {}  pc {}  committed {}  IPC {:.3}  [{}  {}ms]",
+            cpu.cycles(), cpu.pc(), stats.committed, stats.ipc, if running { "running" } else { "paused" }, delay
+        ), width, ' '),
+        fit("space/n: step  c: step to commit  r: run/pause  +/-: speed  q: quit", width, ' ')
+    ];
+
+    let queue = cpu.pending_insts().take(12).enumerate()
+        .map(|(i, inst)| format!("{:>5} {}", cpu.pc() + i, inst))
+        .collect();
+    let rob = cpu.reorder_buffer().iter().enumerate()
+        .map(|(i, rob)| format!("#{:<2}{}", i, rob))
+        .collect();
+    screen.extend(hstack(vec![
+        panel("Instruction Queue", queue, 26, 12),
+        panel("ROB  (tag busy ready pc inst dest value addr)", rob, width.saturating_sub(29), 12)
+    ]));
+
+    let rs = cpu.reservation_stations().iter().enumerate()
+        .map(|(i, rs)| format!("{:<3}{}", i, rs))
+        .collect();
+    screen.extend(panel("Reservation Stations  (type busy exec inst Vj Vk Qj Qk dest addr)", rs, width, 12));
+
+    // 寄存器状态中的标签用 ROB 中的位置表示，便于阅读
+    let rob_pos = |tag: usize| cpu.reorder_buffer().iter().position(|rob| rob.tag() == tag);
+    let reg_stat: Vec<String> = (0..8).map(|row| {
+        (0..4).map(|col| {
+            let reg = row + col * 8;
+            let stat = match cpu.physical_registers() {
+                Some(prf) => format!("P{}", prf.mapping(reg)),
+                None => match cpu.register_status()[reg].reorder().and_then(rob_pos) {
+                    Some(pos) => format!("#{}", pos),
+                    None => "-".to_string()
+                }
+            };
+            format!("R{:<3}{:>4}{:>8}", reg, stat, cpu.regs()[reg])
+        }).collect::<Vec<_>>().join("  ")
+    }).collect();
+    let units = cpu.exec_units().iter().enumerate()
+        .map(|(i, unit)| format!("{:<3}{}", i, unit))
+        .collect();
+    let events = cpu.last_events();
+    let cdb = events.fetched.iter().map(|(pc, inst)| format!("fetch  [{}] {}", pc, inst))
+        .chain(events.issued.iter().map(|(pc, inst)| format!("issue  [{}] {}", pc, inst)))
+        .chain(events.dispatched.iter().map(|(pc, inst)| format!("exec   [{}] {}", pc, inst)))
+        .chain(events.written_back.iter().map(|item| format!("cdb    [{}] {} -> {}", item.pc, item.inst, item.value)))
+        .chain(events.committed.iter().map(|item| format!("commit [{}] {}", item.pc, item.inst)))
+        .collect();
+    screen.extend(hstack(vec![
+        panel("Register Status / Value", reg_stat, 84, 11),
+        panel("Exec Units", units, 32, 11),
+        panel("Events", cdb, width.saturating_sub(84 + 32 + 6), 11)
+    ]));
+    screen.into_iter().map(|line| line.chars().take(width).collect()).collect()
+}
+
+fn draw(out: &mut impl Write, lines: &[String]) -> io::Result<()> {
+    queue!(out, cursor::MoveTo(0, 0), terminal::Clear(terminal::ClearType::All))?;
+    for (row, line) in lines.iter().enumerate() {
+        queue!(out, cursor::MoveTo(0, row as u16), Print(line))?;
+    }
+    out.flush()
+}
+
+fn ui(cpu: &mut TomasuloCpu) -> io::Result<()> {
+    let mut out = io::stdout();
+    let mut running = false;
+    let mut delay: u64 = 200;
+    loop {
+        let (width, _) = terminal::size()?;
+        draw(&mut out, &render(cpu, running, delay, width as usize))?;
+        let timeout = if running && !cpu.done() { Duration::from_millis(delay) } else { Duration::from_secs(3600) };
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press { continue; }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Char(' ') | KeyCode::Char('n') | KeyCode::Right => { cpu.run_for(1); },
+                    KeyCode::Char('c') => {
+                        let committed = cpu.committed();
+                        cpu.run_until(|cpu| cpu.committed() > committed);
+                    },
+                    KeyCode::Char('r') => running = !running,
+                    KeyCode::Char('+') => delay = (delay / 2).max(10),
+                    KeyCode::Char('-') => delay = (delay * 2).min(5000),
+                    _ => {}
+                }
+            }
+        }else if running {
+            cpu.run_for(1);
+        }
+    }
+    Ok(())
+}
+
+/// 用法: tui [inst file] [data file] [--init random[:seed]|zero|file]
+fn main() -> io::Result<()> {
+    let mut args: Vec<String> = std::env::args().collect();
+    let init = InitMode::from_args(&args).unwrap();
+    if let Some(pos) = args.iter().position(|arg| arg == "--init") {
+        args.drain(pos..args.len().min(pos + 2));
+    }
+    let inst_file = args.get(1).map(|s| s.as_str()).unwrap_or("inst.txt");
+    let data_file = args.get(2).map(|s| s.as_str()).unwrap_or("data.txt");
+
+    let mut trace = Trace::new("traces/tui.txt");
+    let mut cpu = TomasuloCpu::new(&mut trace);
+    let parser = Parser::new();
+    parser.read_inst(&mut cpu, inst_file).expect("Fail to read instruction");
+    parser.read_data(&mut cpu, data_file).expect("Fail to read data");
+    parser.init(&mut cpu, &init).expect("Fail to initialize registers");
+
+    terminal::enable_raw_mode()?;
+    execute!(io::stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+    let res = ui(&mut cpu);
+    execute!(io::stdout(), terminal::LeaveAlternateScreen, cursor::Show)?;
+    terminal::disable_raw_mode()?;
+    res
+}
@@ -0,0 +1,549 @@
+use std::{fmt, str::FromStr};
+
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use serde::{ Serialize, Deserialize };
+
+use super::{ CacheStats, MshrStats };
+
+/// 默认的主存访问延迟（周期数）
+pub const DEFAULT_MEMORY_LATENCY: usize = 50;
+
+/// 组内的替换策略
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Replacement {
+    /// 最近最少使用
+    Lru,
+    /// 先进先出
+    Fifo,
+    /// 随机替换，参数为随机数种子
+    Random(u64)
+}
+
+impl FromStr for Replacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lru" => Ok(Replacement::Lru),
+            "fifo" => Ok(Replacement::Fifo),
+            _ => match s.strip_prefix("random") {
+                Some("") => Ok(Replacement::Random(0)),
+                Some(seed) => seed.trim_start_matches(':').parse()
+                    .map(Replacement::Random)
+                    .map_err(|_| format!("invalid seed: {}", seed)),
+                None => Err(format!("unknown replacement policy: {}", s))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Replacement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Replacement::Lru => write!(f, "lru"),
+            Replacement::Fifo => write!(f, "fifo"),
+            Replacement::Random(seed) => write!(f, "random:{}", seed)
+        }
+    }
+}
+
+/// 一级 cache 的配置
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// 容量（字节）
+    pub size: usize,
+    /// 相联度
+    pub assoc: usize,
+    /// 块大小（字节）
+    pub line_size: usize,
+    /// 替换策略
+    pub replacement: Replacement,
+    /// 命中延迟（周期数）
+    pub hit_latency: usize
+}
+
+impl CacheConfig {
+    /// 组数，相联度或块大小为 0 时为 0
+    pub fn sets(&self) -> usize {
+        self.assoc.checked_mul(self.line_size)
+            .and_then(|set_size| self.size.checked_div(set_size))
+            .unwrap_or(0)
+    }
+
+    /// 检查配置是否可以构成至少一组 cache
+    pub fn validate(&self) -> Result<(), String> {
+        if self.assoc == 0 {
+            return Err("cache associativity must be positive".to_string())
+        }
+        if self.line_size == 0 {
+            return Err("cache line size must be positive".to_string())
+        }
+        if self.sets() == 0 {
+            return Err(format!("cache too small: {} bytes for {} ways of {} bytes", self.size, self.assoc, self.line_size))
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CacheConfig {
+    type Err = String;
+
+    /// 解析 `size,assoc,line_size,replacement,hit_latency` 格式，例如 `32768,4,64,lru,2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let items: Vec<&str> = s.split(',').map(|item| item.trim()).collect();
+        let [size, assoc, line_size, replacement, hit_latency] = items.as_slice() else {
+            return Err(format!("expect size,assoc,line_size,replacement,hit_latency: {}", s))
+        };
+        let number = |item: &str| item.parse::<usize>().map_err(|_| format!("invalid number: {}", item));
+        let config = Self{
+            size: number(size)?,
+            assoc: number(assoc)?,
+            line_size: number(line_size)?,
+            replacement: replacement.parse()?,
+            hit_latency: number(hit_latency)?
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// L1 的写策略
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WritePolicy {
+    /// 写回（只写 cache，替换时再写回下一级），否则为写直达
+    pub write_back: bool,
+    /// 写缺失时调入该块，否则直接写下一级
+    pub write_allocate: bool
+}
+
+impl Default for WritePolicy {
+    fn default() -> Self {
+        Self{ write_back: true, write_allocate: true }
+    }
+}
+
+impl FromStr for WritePolicy {
+    type Err = String;
+
+    /// 解析 `wb|wt,wa|nwa` 格式，例如 `wt,nwa`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hit, miss) = s.split_once(',').ok_or(format!("expect wb|wt,wa|nwa: {}", s))?;
+        let write_back = match hit.trim() {
+            "wb" => true,
+            "wt" => false,
+            _ => return Err(format!("unknown write hit policy: {}", hit))
+        };
+        let write_allocate = match miss.trim() {
+            "wa" => true,
+            "nwa" => false,
+            _ => return Err(format!("unknown write miss policy: {}", miss))
+        };
+        Ok(Self{ write_back, write_allocate })
+    }
+}
+
+impl fmt::Display for WritePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{},{}",
+            if self.write_back { "wb" } else { "wt" },
+            if self.write_allocate { "wa" } else { "nwa" }
+        )
+    }
+}
+
+/// 一次访问 cache 的结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lookup {
+    Hit,
+    Miss,
+    /// 缺失且被替换的块是脏的，参数为被替换块的地址
+    DirtyMiss(u32)
+}
+
+impl Lookup {
+    pub fn hit(&self) -> bool {
+        *self == Lookup::Hit
+    }
+}
+
+/// cache 中的一块
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Line {
+    tag: u32,
+    /// 被写过且还未写回下一级
+    dirty: bool,
+    /// 最后一次访问的时间
+    last_used: usize,
+    /// 调入的时间
+    filled_at: usize
+}
+
+/// 组相联 cache，只模拟命中与缺失，数据仍然保存在 `Memory` 中
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cache {
+    name: String,
+    config: CacheConfig,
+    sets: Vec<Vec<Line>>,
+    /// 访问计数，用于 LRU 与 FIFO
+    tick: usize,
+    rng: ChaCha12Rng,
+    hits: usize,
+    misses: usize,
+    /// 替换出脏块的次数
+    writebacks: usize
+}
+
+impl Cache {
+    /// 创建一级 cache，配置无法构成至少一组 cache 时返回错误
+    pub fn new<S: Into<String>>(name: S, config: CacheConfig) -> Result<Self, String> {
+        config.validate()?;
+        let seed = match config.replacement {
+            Replacement::Random(seed) => seed,
+            _ => 0
+        };
+        Ok(Self {
+            name: name.into(),
+            config,
+            sets: vec![vec![]; config.sets()],
+            tick: 0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            hits: 0,
+            misses: 0,
+            writebacks: 0
+        })
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    /// 地址所在块是否在 cache 中，不改变 cache 的状态
+    pub fn contains(&self, addr: u32) -> bool {
+        let (set, tag) = self.locate(addr);
+        self.sets[set].iter().any(|line| line.tag == tag)
+    }
+
+    /// 地址对应的 (组号, 标记)
+    fn locate(&self, addr: u32) -> (usize, u32) {
+        let block = addr as usize / self.config.line_size;
+        let sets = self.sets.len();
+        (block % sets, (block / sets) as u32)
+    }
+
+    /// 访问地址，缺失时调入该块；dirty 为 true 表示写入并将该块标记为脏
+    pub(crate) fn access(&mut self, addr: u32, dirty: bool) -> Lookup {
+        if self.update(addr, dirty) {
+            return Lookup::Hit
+        }
+        let (set, tag) = self.locate(addr);
+        let line = Line{ tag, dirty, last_used: self.tick, filled_at: self.tick };
+        if self.sets[set].len() < self.config.assoc {
+            self.sets[set].push(line);
+            return Lookup::Miss
+        }
+        let victim = match self.config.replacement {
+            Replacement::Lru => (0..self.config.assoc).min_by_key(|way| self.sets[set][*way].last_used).unwrap(),
+            Replacement::Fifo => (0..self.config.assoc).min_by_key(|way| self.sets[set][*way].filled_at).unwrap(),
+            Replacement::Random(_) => self.rng.gen_range(0..self.config.assoc)
+        };
+        let evicted = std::mem::replace(&mut self.sets[set][victim], line);
+        if evicted.dirty {
+            self.writebacks += 1;
+            let block = evicted.tag as usize * self.sets.len() + set;
+            Lookup::DirtyMiss((block * self.config.line_size) as u32)
+        }else {
+            Lookup::Miss
+        }
+    }
+
+    /// 访问地址，缺失时不调入该块，返回是否命中
+    pub(crate) fn update(&mut self, addr: u32, dirty: bool) -> bool {
+        self.tick += 1;
+        let (set, tag) = self.locate(addr);
+        let tick = self.tick;
+        match self.sets[set].iter_mut().find(|line| line.tag == tag) {
+            Some(line) => {
+                line.last_used = tick;
+                line.dirty |= dirty;
+                self.hits += 1;
+                true
+            },
+            None => {
+                self.misses += 1;
+                false
+            }
+        }
+    }
+
+    /// 记录一次合并到 MSHR 的次级缺失，dirty 为 true 表示写入该块
+    pub(crate) fn record_secondary_miss(&mut self, addr: u32, dirty: bool) {
+        self.misses += 1;
+        let (set, tag) = self.locate(addr);
+        if let Some(line) = self.sets[set].iter_mut().find(|line| line.tag == tag) {
+            line.dirty |= dirty;
+        }
+    }
+
+    /// 地址所在块的编号
+    pub fn block(&self, addr: u32) -> u32 {
+        addr / self.config.line_size as u32
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats::new(&self.name, self.hits, self.misses, self.writebacks)
+    }
+}
+
+/// 缺失状态保持寄存器（MSHR），记录一次尚未完成的 L1 缺失
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Mshr {
+    /// 缺失的块编号
+    pub block: u32,
+    /// 块调入完成的周期
+    pub ready_at: usize,
+    /// 合并到该项的次级缺失个数
+    pub merged: usize
+}
+
+/// L1 与可选的 L2 组成的 cache 层次，缺失时逐级向下访问，
+/// 访问延迟为经过的各级命中延迟之和，最后一级缺失时再加上主存延迟，
+/// 被替换的脏块先写回下一级，其延迟也计入本次访问。
+/// L1 的写策略可以配置，L2 总是写回、写分配。
+/// 设置 MSHR 个数后 L1 为非阻塞 cache：同时未完成的缺失数受 MSHR 个数限制，
+/// 对同一块的次级缺失合并到已有的 MSHR，等待该块调入
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheHierarchy {
+    l1: Cache,
+    l2: Option<Cache>,
+    /// 主存访问延迟
+    memory_latency: usize,
+    /// L1 的写策略
+    write_policy: WritePolicy,
+    /// MSHR 个数，None 表示不限制未完成的缺失，也不合并次级缺失
+    mshr_count: Option<usize>,
+    /// 未完成的缺失
+    mshrs: Vec<Mshr>,
+    mshr_stats: MshrStats,
+    /// 至少有一个缺失未完成的周期内未完成的缺失数之和，以及这样的周期数，用于计算访存并行度
+    outstanding_sum: usize,
+    outstanding_cycles: usize,
+    /// 访问次数与总延迟，用于计算平均访存延迟
+    accesses: usize,
+    total_latency: usize
+}
+
+impl CacheHierarchy {
+    /// 创建 cache 层次，任意一级的配置无效时返回错误
+    pub fn new(l1: CacheConfig, l2: Option<CacheConfig>, memory_latency: usize) -> Result<Self, String> {
+        Ok(Self {
+            l1: Cache::new("L1", l1)?,
+            l2: l2.map(|l2| Cache::new("L2", l2)).transpose()?,
+            memory_latency,
+            write_policy: WritePolicy::default(),
+            mshr_count: None,
+            mshrs: vec![],
+            mshr_stats: MshrStats::default(),
+            outstanding_sum: 0,
+            outstanding_cycles: 0,
+            accesses: 0,
+            total_latency: 0
+        })
+    }
+
+    pub fn l1(&self) -> &Cache {
+        &self.l1
+    }
+
+    pub fn l2(&self) -> Option<&Cache> {
+        self.l2.as_ref()
+    }
+
+    /// 设置 L1 的写策略，默认为写回、写分配
+    pub fn set_write_policy(&mut self, policy: WritePolicy) {
+        self.write_policy = policy;
+    }
+
+    pub fn write_policy(&self) -> WritePolicy {
+        self.write_policy
+    }
+
+    /// 设置 MSHR 个数，None 表示不模拟 MSHR
+    pub fn set_mshrs(&mut self, count: Option<usize>) -> Result<(), String> {
+        if count == Some(0) {
+            return Err("MSHR count must be positive".to_string())
+        }
+        self.mshr_count = count;
+        self.mshr_stats.count = count.unwrap_or(0);
+        Ok(())
+    }
+
+    /// 当前未完成的缺失
+    pub fn mshrs(&self) -> &[Mshr] {
+        &self.mshrs
+    }
+
+    /// 在第 now 个周期依次访问一次访存的各个部分（拆分的非对齐访问有两个部分），返回总的延迟。
+    /// 各部分的主缺失所需的 MSHR 不能同时满足时不访问任何部分，返回 None；
+    /// 所需的 MSHR 多于 MSHR 总数时，等所有 MSHR 空闲后由一个 MSHR 依次调入各块
+    pub(crate) fn access_parts(&mut self, parts: &[u32], now: usize, write: bool) -> Option<usize> {
+        let access = |cache: &mut Self, part: u32| if write { cache.write(part, now) } else { cache.read(part, now) };
+        let Some(count) = self.mshr_count.filter(|_| !write || self.write_policy.write_allocate) else {
+            return parts.iter().try_fold(0, |latency, part| access(self, *part).map(|part_latency| latency + part_latency))
+        };
+        self.mshrs.retain(|mshr| mshr.ready_at > now);
+        // 前面的部分缺失时可能替换出后面部分所在的块，因此之后的部分都按缺失计算
+        let mut blocks: Vec<u32> = self.mshrs.iter().map(|mshr| mshr.block).collect();
+        let mut needed = 0;
+        for part in parts {
+            let block = self.l1.block(*part);
+            if !blocks.contains(&block) && (needed > 0 || !self.l1.contains(*part)) {
+                blocks.push(block);
+                needed += 1;
+            }
+        }
+        let sequential = needed > count;
+        if (sequential && !self.mshrs.is_empty()) || (!sequential && self.mshrs.len() + needed > count) {
+            self.mshr_stats.full += 1;
+            return None
+        }
+        if !sequential {
+            return parts.iter().try_fold(0, |latency, part| access(self, *part).map(|part_latency| latency + part_latency))
+        }
+        // 第一个部分分配唯一的 MSHR，之后的部分在它调入之后依次调入
+        let mut latency = access(self, parts[0])?;
+        for part in &parts[1..] {
+            latency += self.lookup(*part, write).0;
+        }
+        if let Some(mshr) = self.mshrs.last_mut() {
+            mshr.ready_at = now + latency;
+        }
+        Some(latency)
+    }
+
+    /// 在第 now 个周期读地址，返回访问延迟；没有空闲的 MSHR 时返回 None，访问需要下个周期重试
+    pub(crate) fn read(&mut self, addr: u32, now: usize) -> Option<usize> {
+        self.access(addr, now, false)
+    }
+
+    /// 在第 now 个周期写地址，返回访问延迟；写分配时与读一样可能因没有空闲的 MSHR 而返回 None
+    pub(crate) fn write(&mut self, addr: u32, now: usize) -> Option<usize> {
+        if self.write_policy.write_allocate {
+            return self.access(addr, now, true)
+        }
+        // 写不分配：命中时写 L1，缺失或写直达时写下一级
+        let mut latency = self.l1.config.hit_latency;
+        if !self.l1.update(addr, self.write_policy.write_back) || !self.write_policy.write_back {
+            latency += self.write_next(addr);
+        }
+        self.accesses += 1;
+        self.total_latency += latency;
+        Some(latency)
+    }
+
+    fn access(&mut self, addr: u32, now: usize, write: bool) -> Option<usize> {
+        let Some(count) = self.mshr_count else {
+            return Some(self.lookup(addr, write).0)
+        };
+        self.mshrs.retain(|mshr| mshr.ready_at > now);
+        let block = self.l1.block(addr);
+        let hit_latency = self.l1.config.hit_latency;
+        if let Some(mshr) = self.mshrs.iter_mut().find(|mshr| mshr.block == block) {
+            // 次级缺失：等待正在调入的块
+            mshr.merged += 1;
+            self.mshr_stats.merged += 1;
+            self.l1.record_secondary_miss(addr, write && self.write_policy.write_back);
+            let mut latency = (mshr.ready_at - now).max(hit_latency);
+            if write && !self.write_policy.write_back {
+                latency += self.write_next(addr);
+            }
+            self.accesses += 1;
+            self.total_latency += latency;
+            return Some(latency)
+        }
+        if !self.l1.contains(addr) && self.mshrs.len() == count {
+            self.mshr_stats.full += 1;
+            return None
+        }
+        let (latency, hit) = self.lookup(addr, write);
+        // 只有 L1 缺失才分配 MSHR，写直达或写回脏块的延迟不算缺失
+        if !hit {
+            self.mshrs.push(Mshr{ block, ready_at: now + latency, merged: 0 });
+            self.mshr_stats.primary += 1;
+            self.mshr_stats.peak = self.mshr_stats.peak.max(self.mshrs.len());
+        }
+        Some(latency)
+    }
+
+    /// 每周期采样未完成的缺失个数，用于计算访存并行度
+    pub(crate) fn sample(&mut self, now: usize) {
+        self.mshrs.retain(|mshr| mshr.ready_at > now);
+        if !self.mshrs.is_empty() {
+            self.outstanding_sum += self.mshrs.len();
+            self.outstanding_cycles += 1;
+        }
+    }
+
+    /// MSHR 统计，没有设置 MSHR 时为 None
+    pub fn mshr_stats(&self) -> Option<MshrStats> {
+        self.mshr_count.map(|_| {
+            let mut stats = self.mshr_stats.clone();
+            if self.outstanding_cycles > 0 {
+                stats.mlp = self.outstanding_sum as f64 / self.outstanding_cycles as f64;
+            }
+            stats
+        })
+    }
+
+    /// 逐级访问地址（缺失时调入），返回访问延迟以及 L1 是否命中
+    fn lookup(&mut self, addr: u32, write: bool) -> (usize, bool) {
+        let mut latency = self.l1.config.hit_latency;
+        let lookup = self.l1.access(addr, write && self.write_policy.write_back);
+        let hit = matches!(lookup, Lookup::Hit);
+        match lookup {
+            Lookup::Hit => {},
+            lookup => {
+                if let Lookup::DirtyMiss(victim) = lookup {
+                    latency += self.write_next(victim);
+                }
+                latency += match &mut self.l2 {
+                    Some(l2) => l2.config.hit_latency + match l2.access(addr, false) {
+                        Lookup::Hit => 0,
+                        Lookup::Miss => self.memory_latency,
+                        Lookup::DirtyMiss(_) => 2 * self.memory_latency
+                    },
+                    None => self.memory_latency
+                };
+            }
+        }
+        if write && !self.write_policy.write_back {
+            latency += self.write_next(addr);
+        }
+        self.accesses += 1;
+        self.total_latency += latency;
+        (latency, hit)
+    }
+
+    /// 将地址所在块写到 L1 的下一级，返回写入的延迟
+    fn write_next(&mut self, addr: u32) -> usize {
+        match &mut self.l2 {
+            Some(l2) => l2.config.hit_latency + match l2.access(addr, true) {
+                Lookup::Hit => 0,
+                Lookup::Miss => self.memory_latency,
+                Lookup::DirtyMiss(_) => 2 * self.memory_latency
+            },
+            None => self.memory_latency
+        }
+    }
+
+    /// 各级 cache 的统计信息
+    pub fn stats(&self) -> Vec<CacheStats> {
+        std::iter::once(&self.l1).chain(self.l2.iter()).map(|cache| cache.stats()).collect()
+    }
+
+    /// 平均访存延迟
+    pub fn avg_latency(&self) -> f64 {
+        if self.accesses > 0 { self.total_latency as f64 / self.accesses as f64 } else { 0.0 }
+    }
+}
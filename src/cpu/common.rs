@@ -1,8 +1,9 @@
 use std::collections::VecDeque;
 use std::io::Write;
 use crate::trace::Trace;
+use serde::{ Serialize, Deserialize };
 
-use super::{ Instruction, Cpu, memory::Memory };
+use super::{ Instruction, Cpu, ArchState, Stats, Retired, Endian, Misaligned, MemFault, MemoryMap, Device, memory::Memory, stats::StatsAccumulators, format_regs };
 
 pub const ADD_CYCLES: usize = 2;
 pub const SUB_CYCLES: usize = 2;
@@ -17,56 +18,32 @@ pub struct SingleCycleCpu<'a> {
     pub(crate) instruction_queue: VecDeque<Instruction>,
     pub(crate) memory: Memory,
     pub(crate) trace: &'a mut Trace,
-    pub(crate) cycles: usize
+    pub(crate) cycles: usize,
+    /// 下一条执行的指令序号
+    pub(crate) pc: usize,
+    /// 统计信息
+    pub(crate) stats: Stats
+}
+
+/// 检查点：除追踪文件与挂载的设备之外的全部状态
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    regs: [i32; 32],
+    instruction_queue: VecDeque<Instruction>,
+    memory: Memory,
+    cycles: usize,
+    pc: usize,
+    stats: Stats,
+    /// 统计信息中不参与序列化的累加量
+    stats_accumulators: StatsAccumulators
 }
 
 impl<'a> Cpu for SingleCycleCpu<'a> {
-    fn run(&mut self) {
+    fn run(&mut self) -> Stats {
         println!("Start execute instructions!");
-        loop {
-            if let Some(inst) = self.instruction_queue.pop_front() {
-                match inst {
-                    Instruction::Add(operand) => { 
-                        self.regs[operand.target as usize] = if let Some(add_res) = self.regs[operand.operand1 as usize].checked_add(self.regs[operand.operand2 as usize]){ add_res }else { 0 };
-                        self.cycles += ADD_CYCLES; 
-                    },
-                    Instruction::Sub(operand) => { 
-                        self.regs[operand.target as usize] = if let Some(sub_res) = self.regs[operand.operand1 as usize].checked_sub(self.regs[operand.operand2 as usize]){ sub_res }else { 0 };
-                        self.cycles += SUB_CYCLES; 
-                    },
-                    Instruction::Mul(operand) => { 
-                        self.regs[operand.target as usize] = if let Some(mul_res) = self.regs[operand.operand1 as usize].checked_mul(self.regs[operand.operand2 as usize]){ mul_res }else { 0 };
-                        self.cycles += MUL_CYCLES; 
-                    },
-                    Instruction::Div(operand) => { 
-                        self.regs[operand.target as usize] = if let Some(div_res) = self.regs[operand.operand1 as usize].checked_div(self.regs[operand.operand2 as usize]){ div_res }else { 0 };
-                        self.cycles += DIV_CYCLES; 
-                    },
-                    Instruction::Ld(reg1, reg2, imm) => {
-                        let addr = (self.regs[reg2] + (imm as i32)) as u32;
-                        let val = self.memory.read(addr);
-                        self.regs[reg1] = val;
-                        self.cycles += LOAD_CYCLES;
-                    },
-                    Instruction::Sd(reg1, reg2, imm) => {
-                        let addr = (self.regs[reg2] + (imm as i32)) as u32;
-                        self.memory.write(addr, self.regs[reg1]);
-                    },
-
-                    Instruction::Jump(_, _) => {
-                        self.cycles += JUMP_CYCLES;
-                    }
-                    _ => {}
-                }
-            }else{ break; }
-            let mut info: String = String::new();
-            for (index, reg) in self.regs.iter().enumerate() {
-                info.push_str(format!("reg{}: {}; ", index, reg).as_str());
-            }
-            self.trace(info);
-        }
-        println!("[Debug] cycles: {}", self.cycles);
+        while self.step().is_some() {}
         println!("Finish execute!");
+        self.stats()
     }
 
     fn add_inst(&mut self, inst: Instruction) {
@@ -84,6 +61,18 @@ impl<'a> Cpu for SingleCycleCpu<'a> {
         self.memory.write(addr, val);
     }
 
+    fn set_regs(&mut self, index: usize, number: i32) {
+        self.regs[index] = number;
+    }
+
+    fn state(&self) -> ArchState {
+        ArchState::new(self.regs, self.memory.words())
+    }
+
+    fn memory_map(&self) -> Option<&MemoryMap> {
+        self.memory.map()
+    }
+
 }
 
 impl<'a> SingleCycleCpu<'a> {
@@ -92,13 +81,163 @@ impl<'a> SingleCycleCpu<'a> {
             regs: [0i32;32],
             instruction_queue: VecDeque::new(),
             memory: Memory::init(),
-            trace: trace,
-            cycles: 0
+            trace,
+            cycles: 0,
+            pc: 0,
+            stats: Stats::new()
         }
     }
 
-    pub fn set_regs(&mut self, index: usize, number: i32) {
-        self.regs[index] = number;
+    /// 设置内存的字节序
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.memory.set_endian(endian);
+    }
+
+    /// 设置非对齐访存的处理方式
+    pub fn set_misaligned(&mut self, misaligned: Misaligned) {
+        self.memory.set_misaligned(misaligned);
+    }
+
+    /// 设置内存映射，None 表示所有地址都可以读写
+    pub fn set_memory_map(&mut self, map: Option<MemoryMap>) {
+        self.memory.set_map(map);
+    }
+
+    /// 在 base 处挂载内存映射的设备
+    pub fn attach_device(&mut self, base: u32, device: Box<dyn Device>) -> Result<(), String> {
+        self.memory.attach(base, device)
+    }
+
+    /// 设备（控制台）的输出
+    pub fn output(&self) -> String {
+        self.memory.output()
+    }
+
+    /// 执行一条指令，指令队列为空、发生异常或者程序退出后返回 None
+    pub fn step(&mut self) -> Option<Retired> {
+        if self.done() {
+            return None
+        }
+        let inst = self.instruction_queue.pop_front()?;
+        self.memory.set_cycle(self.cycles);
+        let mut retired = Retired{ pc: self.pc, inst, reg_write: None, address: None };
+        match inst {
+            Instruction::Add(operand) => { 
+                self.regs[operand.target] = self.regs[operand.operand1].checked_add(self.regs[operand.operand2]).unwrap_or_default();
+                retired.reg_write = Some((operand.target, self.regs[operand.target]));
+                self.cycles += ADD_CYCLES; 
+            },
+            Instruction::Sub(operand) => { 
+                self.regs[operand.target] = self.regs[operand.operand1].checked_sub(self.regs[operand.operand2]).unwrap_or_default();
+                retired.reg_write = Some((operand.target, self.regs[operand.target]));
+                self.cycles += SUB_CYCLES; 
+            },
+            Instruction::Mul(operand) => { 
+                self.regs[operand.target] = self.regs[operand.operand1].checked_mul(self.regs[operand.operand2]).unwrap_or_default();
+                retired.reg_write = Some((operand.target, self.regs[operand.target]));
+                self.cycles += MUL_CYCLES; 
+            },
+            Instruction::Div(operand) => { 
+                self.regs[operand.target] = self.regs[operand.operand1].checked_div(self.regs[operand.operand2]).unwrap_or_default();
+                retired.reg_write = Some((operand.target, self.regs[operand.target]));
+                self.cycles += DIV_CYCLES; 
+            },
+            Instruction::Ld(reg1, reg2, imm, width) => {
+                let addr = (self.regs[reg2] + (imm as i32)) as u32;
+                let accesses = match self.memory.check(addr, width, false) {
+                    Ok(accesses) => accesses,
+                    Err(fault) => return self.raise(inst, fault)
+                };
+                let val = self.memory.load(addr, width).unwrap();
+                self.regs[reg1] = val;
+                retired.reg_write = Some((reg1, val));
+                retired.address = Some(addr);
+                self.cycles += LOAD_CYCLES * accesses;
+            },
+            Instruction::Sd(reg1, reg2, imm, width) => {
+                let addr = (self.regs[reg2] + (imm as i32)) as u32;
+                let width = width.into();
+                let accesses = match self.memory.check(addr, width, true) {
+                    Ok(accesses) => accesses,
+                    Err(fault) => return self.raise(inst, fault)
+                };
+                self.memory.store(addr, width, self.regs[reg1]).unwrap();
+                retired.address = Some(addr);
+                self.cycles += LOAD_CYCLES * accesses;
+            },
+
+            Instruction::Jump(_, _) => {
+                self.cycles += JUMP_CYCLES;
+            }
+            _ => {}
+        }
+        self.pc += 1;
+        self.stats.record_commit(&inst);
+        self.trace(format_regs(&self.regs));
+        Some(retired)
+    }
+
+    pub fn done(&self) -> bool {
+        self.instruction_queue.is_empty() || self.stats.halted.is_some() || self.memory.exit_code().is_some()
+    }
+
+    /// 访存异常：停止执行，出错的指令不提交并留在指令队列的头部
+    fn raise(&mut self, inst: Instruction, fault: MemFault) -> Option<Retired> {
+        self.instruction_queue.push_front(inst);
+        self.stats.halted = Some(format!("pc {}: {}", self.pc, fault));
+        None
+    }
+
+    /// 保存检查点（JSON 格式），不包括追踪文件与挂载的设备
+    pub fn checkpoint(&self) -> String {
+        let checkpoint = Checkpoint{
+            regs: self.regs,
+            instruction_queue: self.instruction_queue.clone(),
+            memory: self.memory.snapshot(),
+            cycles: self.cycles,
+            pc: self.pc,
+            stats: self.stats.clone(),
+            stats_accumulators: self.stats.accumulators()
+        };
+        serde_json::to_string(&checkpoint).unwrap()
+    }
+
+    /// 从检查点恢复，当前挂载的设备保持不变，设备自身的状态（如控制台输出、退出码）不会恢复
+    pub fn restore(&mut self, checkpoint: &str) -> Result<(), String> {
+        let checkpoint: Checkpoint = serde_json::from_str(checkpoint).map_err(|err| format!("err: {}", err))?;
+        self.regs = checkpoint.regs;
+        self.instruction_queue = checkpoint.instruction_queue;
+        self.memory.restore(checkpoint.memory);
+        self.cycles = checkpoint.cycles;
+        self.pc = checkpoint.pc;
+        self.stats = checkpoint.stats;
+        self.stats.restore_accumulators(checkpoint.stats_accumulators);
+        Ok(())
+    }
+
+    /// 获取当前的统计信息
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        stats.exit_code = self.memory.exit_code();
+        stats.finish(self.cycles);
+        stats
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// 下一条将执行的指令序号
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn regs(&self) -> &[i32; 32] {
+        &self.regs
+    }
+
+    pub fn read_memory(&self, addr: u32) -> i32 {
+        self.memory.read(addr)
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,124 @@
+use std::fmt;
+
+/// 控制台的基地址，写偏移 0 输出一个字符，写偏移 4 以十进制输出一个整数并换行
+pub const CONSOLE_BASE: u32 = 0xffff_0000;
+/// 周期计数器的基地址，读偏移 0、4 分别得到当前周期数的低 32 位与高 32 位
+pub const CYCLE_COUNTER_BASE: u32 = 0xffff_0010;
+/// 停机寄存器的基地址，写入的值作为退出码并停止运行
+pub const HALT_BASE: u32 = 0xffff_0020;
+
+/// 内存映射的 I/O 设备，地址以设备基地址为起点的偏移给出
+pub trait Device: fmt::Debug {
+    fn name(&self) -> &'static str;
+    /// 设备占用的字节数
+    fn size(&self) -> u32;
+    /// 读设备寄存器，cycle 为当前周期数
+    fn read(&self, offset: u32, cycle: usize) -> i32;
+    /// 写设备寄存器，窄于字的写入只保留低位
+    fn write(&mut self, offset: u32, val: i32, cycle: usize);
+    /// 设备请求停机时返回退出码
+    fn exit_code(&self) -> Option<i32> {
+        None
+    }
+    /// 设备已经输出的内容
+    fn output(&self) -> &str {
+        ""
+    }
+}
+
+/// 控制台输出端口
+#[derive(Debug, Default)]
+pub struct Console {
+    output: String
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Device for Console {
+    fn name(&self) -> &'static str {
+        "console"
+    }
+
+    fn size(&self) -> u32 {
+        8
+    }
+
+    fn read(&self, _offset: u32, _cycle: usize) -> i32 {
+        0
+    }
+
+    fn write(&mut self, offset: u32, val: i32, _cycle: usize) {
+        match offset {
+            0..=3 => self.output.push(val as u8 as char),
+            _ => self.output.push_str(format!("{}\n", val).as_str())
+        }
+    }
+
+    fn output(&self) -> &str {
+        &self.output
+    }
+}
+
+/// 只读的周期计数器
+#[derive(Debug, Default)]
+pub struct CycleCounter;
+
+impl Device for CycleCounter {
+    fn name(&self) -> &'static str {
+        "cycle counter"
+    }
+
+    fn size(&self) -> u32 {
+        8
+    }
+
+    fn read(&self, offset: u32, cycle: usize) -> i32 {
+        match offset {
+            0..=3 => cycle as u32 as i32,
+            _ => ((cycle as u64) >> 32) as u32 as i32
+        }
+    }
+
+    fn write(&mut self, _offset: u32, _val: i32, _cycle: usize) {}
+}
+
+/// 停机寄存器，写入退出码后程序结束
+#[derive(Debug, Default)]
+pub struct HaltRegister {
+    exit_code: Option<i32>
+}
+
+impl Device for HaltRegister {
+    fn name(&self) -> &'static str {
+        "halt"
+    }
+
+    fn size(&self) -> u32 {
+        4
+    }
+
+    fn read(&self, _offset: u32, _cycle: usize) -> i32 {
+        self.exit_code.unwrap_or_default()
+    }
+
+    fn write(&mut self, _offset: u32, val: i32, _cycle: usize) {
+        self.exit_code.get_or_insert(val);
+    }
+
+    fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+}
+
+/// 默认的设备：控制台、周期计数器与停机寄存器，均位于标准内存布局的 mmio 区域中
+pub fn standard_devices() -> Vec<(u32, Box<dyn Device>)> {
+    vec![
+        (CONSOLE_BASE, Box::new(Console::new())),
+        (CYCLE_COUNTER_BASE, Box::new(CycleCounter)),
+        (HALT_BASE, Box::new(HaltRegister::default())),
+    ]
+}
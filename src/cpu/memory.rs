@@ -1,28 +1,424 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, BTreeMap};
+use std::{fmt, str::FromStr};
+use serde::{ Serialize, Deserialize };
+use super::device::Device;
 
-/// 使用哈希表模拟内存
-/// key: memory address value: memory value
-pub(crate) struct Memory (HashMap<u32, i32>);
+/// 访存宽度
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Width {
+    /// 有符号字节
+    Byte,
+    /// 无符号字节
+    ByteUnsigned,
+    /// 有符号半字
+    Half,
+    /// 无符号半字
+    HalfUnsigned,
+    /// 字
+    Word
+}
+
+impl Width {
+    /// 访问的字节数
+    pub fn bytes(&self) -> usize {
+        match self {
+            Width::Byte | Width::ByteUnsigned => 1,
+            Width::Half | Width::HalfUnsigned => 2,
+            Width::Word => 4
+        }
+    }
+
+    /// 读出的值是否进行符号扩展
+    pub fn signed(&self) -> bool {
+        !matches!(self, Width::ByteUnsigned | Width::HalfUnsigned)
+    }
+}
+
+/// store 的宽度，写内存时不区分有无符号
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum StoreWidth {
+    Byte,
+    Half,
+    Word
+}
+
+impl From<StoreWidth> for Width {
+    fn from(width: StoreWidth) -> Width {
+        match width {
+            StoreWidth::Byte => Width::Byte,
+            StoreWidth::Half => Width::Half,
+            StoreWidth::Word => Width::Word
+        }
+    }
+}
+
+/// 字节序
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Endian {
+    Little,
+    Big
+}
+
+impl FromStr for Endian {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "little" => Ok(Endian::Little),
+            "big" => Ok(Endian::Big),
+            _ => Err(format!("unknown endian: {}", s))
+        }
+    }
+}
+
+/// 非对齐访存的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Misaligned {
+    /// 产生访存异常
+    Trap,
+    /// 拆分为两次对齐的访问，需要两次访存的时间
+    Split,
+    /// 直接访问，与对齐访问的时间相同
+    Allow
+}
+
+impl FromStr for Misaligned {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "trap" => Ok(Misaligned::Trap),
+            "split" => Ok(Misaligned::Split),
+            "allow" => Ok(Misaligned::Allow),
+            _ => Err(format!("unknown misaligned policy: {}", s))
+        }
+    }
+}
+
+/// 访存异常
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MemFault {
+    /// 非对齐访问，(地址, 字节数)
+    Misaligned(u32, usize),
+    /// 访问的地址不在任何区域中
+    Unmapped(u32),
+    /// 区域不允许该访问，(地址, 是否为写)
+    Protection(u32, bool)
+}
+
+impl fmt::Display for MemFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemFault::Misaligned(addr, bytes) => write!(f, "misaligned {}-byte access at {:#x}", bytes, addr),
+            MemFault::Unmapped(addr) => write!(f, "access to unmapped address {:#x}", addr),
+            MemFault::Protection(addr, write) => {
+                write!(f, "{} access to protected address {:#x}", if *write { "write" } else { "read" }, addr)
+            }
+        }
+    }
+}
+
+/// 解析十进制或 `0x` 开头的十六进制数
+fn parse_number(s: &str) -> Result<u32, String> {
+    let s = s.trim();
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse()
+    }.map_err(|_| format!("invalid number: {}", s))
+}
+
+/// 内存中的一个区域
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    /// 起始地址
+    pub base: u32,
+    /// 大小（字节）
+    pub size: u32,
+    pub read: bool,
+    pub write: bool
+}
+
+impl Region {
+    pub fn new<S: Into<String>>(name: S, base: u32, size: u32, read: bool, write: bool) -> Self {
+        Self{ name: name.into(), base, size, read, write }
+    }
+
+    /// 区域是否包含 [addr, addr + len)
+    pub fn contains(&self, addr: u32, len: usize) -> bool {
+        addr >= self.base && (addr - self.base) as u64 + len as u64 <= self.size as u64
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// 解析 `name:base:size:perm` 格式，perm 由 `r`、`w` 组成，例如 `stack:0x7ff00000:0x100000:rw`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let items: Vec<&str> = s.split(':').collect();
+        let [name, base, size, perm] = items.as_slice() else {
+            return Err(format!("expect name:base:size:perm: {}", s))
+        };
+        if perm.chars().any(|c| c != 'r' && c != 'w') {
+            return Err(format!("invalid permission: {}", perm))
+        }
+        Ok(Self::new(*name, parse_number(base)?, parse_number(size)?, perm.contains('r'), perm.contains('w')))
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{:<8}{:#010x}{:>12}  {}{}", self.name, self.base, self.size,
+            if self.read { "r" } else { "-" }, if self.write { "w" } else { "-" }
+        )
+    }
+}
+
+/// 内存映射，由若干个不重叠的区域组成，不在任何区域中的地址都不可访问
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryMap {
+    regions: Vec<Region>
+}
+
+impl MemoryMap {
+    pub fn new(regions: Vec<Region>) -> Result<Self, String> {
+        for (i, a) in regions.iter().enumerate() {
+            if let Some(b) = regions[i + 1..].iter().find(|b| a.contains(b.base, 1) || b.contains(a.base, 1)) {
+                return Err(format!("region {} overlaps {}", a.name, b.name))
+            }
+        }
+        Ok(Self{ regions })
+    }
+
+    /// 默认的内存布局：text 只读，data、stack 与 MMIO 可读写
+    pub fn standard() -> Self {
+        Self::new(vec![
+            Region::new("data", 0x0000_0000, 0x0010_0000, true, true),
+            Region::new("text", 0x0040_0000, 0x0010_0000, true, false),
+            Region::new("stack", 0x7ff0_0000, 0x0010_0000, true, true),
+            Region::new("mmio", 0xffff_0000, 0x0000_1000, true, true),
+        ]).unwrap()
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// 包含 [addr, addr + len) 的区域
+    pub fn find(&self, addr: u32, len: usize) -> Option<&Region> {
+        self.regions.iter().find(|region| region.contains(addr, len))
+    }
+
+    /// 检查访问是否在某个区域中且有对应的权限
+    pub fn check(&self, addr: u32, len: usize, write: bool) -> Result<&Region, MemFault> {
+        let region = self.find(addr, len).ok_or(MemFault::Unmapped(addr))?;
+        if (write && !region.write) || (!write && !region.read) {
+            return Err(MemFault::Protection(addr, write))
+        }
+        Ok(region)
+    }
+}
+
+impl FromStr for MemoryMap {
+    type Err = String;
+
+    /// `standard` 为默认布局，否则为逗号分隔的区域列表
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "standard" {
+            return Ok(Self::standard())
+        }
+        Self::new(s.split(',').map(|region| region.parse()).collect::<Result<Vec<Region>, String>>()?)
+    }
+}
+
+/// 使用哈希表模拟按字节寻址的内存
+/// key: memory address value: memory byte
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Memory {
+    bytes: HashMap<u32, u8>,
+    endian: Endian,
+    misaligned: Misaligned,
+    /// 内存映射，None 表示所有地址都可以读写
+    map: Option<MemoryMap>,
+    /// 内存映射的设备及其基地址，访问设备的地址不经过内存，不保存在检查点中
+    #[serde(skip)]
+    devices: Vec<(u32, Box<dyn Device>)>,
+    /// 当前周期数，供设备使用
+    cycle: usize
+}
 
 impl Memory {
     pub(crate) fn init() -> Self {
-        Self(HashMap::new())
+        Self{
+            bytes: HashMap::new(),
+            endian: Endian::Little,
+            misaligned: Misaligned::Allow,
+            map: None,
+            devices: Vec::new(),
+            cycle: 0
+        }
+    }
+
+    pub(crate) fn set_endian(&mut self, endian: Endian) {
+        self.endian = endian;
+    }
+
+    pub(crate) fn set_misaligned(&mut self, misaligned: Misaligned) {
+        self.misaligned = misaligned;
+    }
+
+    pub(crate) fn set_map(&mut self, map: Option<MemoryMap>) {
+        self.map = map;
+    }
+
+    pub(crate) fn map(&self) -> Option<&MemoryMap> {
+        self.map.as_ref()
+    }
+
+    pub(crate) fn set_cycle(&mut self, cycle: usize) {
+        self.cycle = cycle;
+    }
+
+    /// 在 base 处挂载设备，有内存映射时设备必须位于某个区域中，设备之间不能重叠
+    pub(crate) fn attach(&mut self, base: u32, device: Box<dyn Device>) -> Result<(), String> {
+        let region = Region::new(device.name(), base, device.size(), true, true);
+        if self.map.as_ref().is_some_and(|map| map.find(base, device.size() as usize).is_none()) {
+            return Err(format!("device {} at {:#x} is not mapped", device.name(), base))
+        }
+        if let Some((_, other)) = self.devices.iter().find(|(other_base, other)| {
+            region.contains(*other_base, 1) || Region::new(other.name(), *other_base, other.size(), true, true).contains(base, 1)
+        }) {
+            return Err(format!("device {} overlaps {}", device.name(), other.name()))
+        }
+        self.devices.push((base, device));
+        Ok(())
+    }
+
+    /// 复制除设备之外的内存状态，用于检查点
+    pub(crate) fn snapshot(&self) -> Memory {
+        Memory{
+            bytes: self.bytes.clone(),
+            endian: self.endian,
+            misaligned: self.misaligned,
+            map: self.map.clone(),
+            devices: Vec::new(),
+            cycle: self.cycle
+        }
+    }
+
+    /// 从检查点恢复内存状态，保留当前挂载的设备
+    pub(crate) fn restore(&mut self, snapshot: Memory) {
+        let devices = std::mem::take(&mut self.devices);
+        *self = snapshot;
+        self.devices = devices;
+    }
+
+    /// 地址所在的设备序号
+    pub(crate) fn device(&self, addr: u32) -> Option<usize> {
+        self.devices.iter().position(|(base, device)| addr >= *base && addr - base < device.size())
+    }
+
+    /// 某个设备请求停机时的退出码
+    pub(crate) fn exit_code(&self) -> Option<i32> {
+        self.devices.iter().find_map(|(_, device)| device.exit_code())
+    }
+
+    /// 所有设备的输出
+    pub(crate) fn output(&self) -> String {
+        self.devices.iter().map(|(_, device)| device.output()).collect()
     }
 
-    /// 从内存中读取数据
-    pub(crate) fn read(&self, mut addr: u32) -> i32 {
-        // 地址必须是 4 字节对齐的
-        // assert_eq!(addr % 4, 0);
-        if addr % 4 != 0 {
-            addr = (addr / 4) * 4;
+    /// 检查访问是否合法，返回需要的访存次数
+    pub(crate) fn check(&self, addr: u32, width: Width, write: bool) -> Result<usize, MemFault> {
+        if let Some(map) = &self.map {
+            map.check(addr, width.bytes(), write)?;
+        }
+        if addr.is_multiple_of(width.bytes() as u32) {
+            return Ok(1)
+        }
+        match self.misaligned {
+            Misaligned::Trap => Err(MemFault::Misaligned(addr, width.bytes())),
+            Misaligned::Split => Ok(2),
+            Misaligned::Allow => Ok(1)
         }
-        if let Some(val) = self.0.get(&addr) { *val }
-        else{ 0 }
     }
 
-    /// 向内存中写写数据
+    /// 按宽度读取数据
+    pub(crate) fn load(&self, addr: u32, width: Width) -> Result<i32, MemFault> {
+        self.check(addr, width, false)?;
+        if let Some(index) = self.device(addr) {
+            let (base, device) = &self.devices[index];
+            let val = device.read(addr - base, self.cycle);
+            return Ok(self.decode(&self.encode(val, width), width))
+        }
+        Ok(self.decode(&self.read_bytes(addr, width.bytes()), width))
+    }
+
+    /// 按宽度写入数据
+    pub(crate) fn store(&mut self, addr: u32, width: Width, val: i32) -> Result<(), MemFault> {
+        self.check(addr, width, true)?;
+        if let Some(index) = self.device(addr) {
+            let val = match width.bytes() {
+                4 => val,
+                bytes => val & ((1 << (8 * bytes)) - 1)
+            };
+            let cycle = self.cycle;
+            let (base, device) = &mut self.devices[index];
+            device.write(addr - *base, val, cycle);
+            return Ok(())
+        }
+        for (i, byte) in self.encode(val, width).into_iter().enumerate() {
+            self.bytes.insert(addr.wrapping_add(i as u32), byte);
+        }
+        Ok(())
+    }
+
+    /// 所有写过的字节所在的字，按对齐的地址排列
+    pub(crate) fn words(&self) -> BTreeMap<u32, i32> {
+        self.bytes.keys().map(|addr| addr & !3).map(|addr| (addr, self.read(addr))).collect()
+    }
+
+    /// 从内存中读取一个字，不检查对齐
+    pub(crate) fn read(&self, addr: u32) -> i32 {
+        self.decode(&self.read_bytes(addr, 4), Width::Word)
+    }
+
+    /// 向内存中写入一个字，不检查对齐，用于初始化内存
     pub(crate) fn write(&mut self, addr: u32, val: i32) {
-        assert_eq!(addr % 4, 0);
-        let _ = self.0.insert(addr, val);
+        for (i, byte) in self.encode(val, Width::Word).into_iter().enumerate() {
+            self.bytes.insert(addr.wrapping_add(i as u32), byte);
+        }
+    }
+
+    /// 读取从 addr 开始的 len 个字节，未写过的字节为 0
+    pub(crate) fn read_bytes(&self, addr: u32, len: usize) -> Vec<u8> {
+        (0..len).map(|i| self.bytes.get(&addr.wrapping_add(i as u32)).copied().unwrap_or(0)).collect()
+    }
+
+    /// 将值的低位按字节序转换为内存中的字节
+    pub(crate) fn encode(&self, val: i32, width: Width) -> Vec<u8> {
+        let bytes = match self.endian {
+            Endian::Little => val.to_le_bytes(),
+            Endian::Big => val.to_be_bytes()
+        };
+        match self.endian {
+            Endian::Little => bytes[..width.bytes()].to_vec(),
+            Endian::Big => bytes[4 - width.bytes()..].to_vec()
+        }
+    }
+
+    /// 将内存中的字节按字节序与宽度转换为值
+    pub(crate) fn decode(&self, bytes: &[u8], width: Width) -> i32 {
+        let val = match self.endian {
+            Endian::Little => bytes.iter().rev().fold(0u32, |val, byte| (val << 8) | *byte as u32),
+            Endian::Big => bytes.iter().fold(0u32, |val, byte| (val << 8) | *byte as u32)
+        };
+        let shift = 32 - 8 * width.bytes() as u32;
+        if width.signed() {
+            ((val << shift) as i32) >> shift
+        }else {
+            val as i32
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,20 +1,77 @@
 pub mod common;
 pub mod tomasulo;
 pub mod memory;
+pub mod stats;
+pub mod rename;
+pub mod scoreboard;
+pub mod pipeline;
+pub mod cache;
+pub mod device;
+pub mod state;
+pub mod sampling;
 pub use common::SingleCycleCpu;
-pub use tomasulo::{ TomasuloCpu, ResStationType };
+pub use scoreboard::ScoreboardCpu;
+pub use pipeline::{ PipelinedCpu, Forwarding };
+pub use tomasulo::{ TomasuloCpu, ResStationType, CdbBroadcast, CycleEvents, Halt, HaltReason, SelectPolicy, CdbArbitration, StageLatency, FrontEnd, RenameMode, StoreEntry };
+pub use rename::PhysRegFile;
+pub use memory::{ Width, StoreWidth, Endian, Misaligned, MemFault, Region, MemoryMap };
+pub use cache::{ Cache, CacheConfig, CacheHierarchy, Replacement, Mshr, WritePolicy };
+pub use device::{ Device, Console, CycleCounter, HaltRegister };
+pub use state::{ ArchState, StateDiff };
+pub use sampling::Sampling;
+pub use stats::{ Stats, StallStats, SlotStats, RsStateStats, CacheStats, MshrStats, SamplingStats, IssueSlot, RsState };
 use memory::Memory;
+use std::fmt;
+use serde::{ Serialize, Deserialize };
 
 /// CPU 的 Trait
 pub trait Cpu{
-    fn run(&mut self);
+    /// 运行所有指令并返回统计信息
+    fn run(&mut self) -> Stats;
     fn add_inst(&mut self, inst: Instruction);
     fn trace<S>(&mut self, s: S) where S: Into<String>;
     fn write_memory(&mut self, addr: u32, val: i32);
+    /// 设置体系结构寄存器的值，需要在运行之前设置
+    fn set_regs(&mut self, index: usize, number: i32);
+    /// 当前的体系结构状态（已提交的寄存器与写过的内存）
+    fn state(&self) -> ArchState;
+    /// 内存映射，用于检查初始化数据的地址，None 表示所有地址都可以访问
+    fn memory_map(&self) -> Option<&MemoryMap> {
+        None
+    }
 }
 
-/// 操作数
+/// 追踪文件中寄存器文件的格式
+pub(crate) fn format_regs(regs: &[i32; 32]) -> String {
+    let mut info: String = String::new();
+    for (index, reg) in regs.iter().enumerate() {
+        info.push_str(format!("reg{}: {}; ", index, reg).as_str());
+    }
+    info
+}
+
+/// 将 Option 格式化为字符串，None 显示为 `-`
+pub(crate) fn opt<T: fmt::Display>(item: &Option<T>) -> String {
+    match item {
+        Some(item) => item.to_string(),
+        None => "-".to_string()
+    }
+}
+
+/// 已提交（退休）的指令信息，用于调试
 #[derive(Debug, Clone, Copy)]
+pub struct Retired {
+    /// 指令在程序中的序号
+    pub pc: usize,
+    pub inst: Instruction,
+    /// 写回的寄存器编号以及写入的值
+    pub reg_write: Option<(usize, i32)>,
+    /// 访存指令的地址
+    pub address: Option<u32>
+}
+
+/// 操作数
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Operand {
     target: usize,
     operand1: usize,
@@ -31,18 +88,77 @@ impl Operand {
     }
 }
 /// 指令类型
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum Instruction{
     Add(Operand),
     Sub(Operand),
     Mul(Operand),
     Div(Operand),
-    Ld(usize, usize, u32),
-    Sd(usize, usize, u32),
+    /// 按宽度读内存，reg1 <- mem[reg2 + imm]
+    Ld(usize, usize, u32, Width),
+    /// 按宽度写内存，mem[reg2 + imm] <- reg1
+    Sd(usize, usize, u32, StoreWidth),
     /// 这里的 Jump 并不做实际跳转，只是
     /// 当做没有目标寄存器的指令
     Jump(usize, usize),
     Invalid
 }
 
+impl Instruction {
+    /// 指令助记符，用于统计与打印
+    pub fn name(&self) -> &'static str {
+        match self {
+            Instruction::Add(_) => "ADD",
+            Instruction::Sub(_) => "SUB",
+            Instruction::Mul(_) => "MUL",
+            Instruction::Div(_) => "DIV",
+            Instruction::Ld(_, _, _, width) => match width {
+                Width::Byte => "LB",
+                Width::ByteUnsigned => "LBU",
+                Width::Half => "LH",
+                Width::HalfUnsigned => "LHU",
+                Width::Word => "LD"
+            },
+            Instruction::Sd(_, _, _, width) => match width {
+                StoreWidth::Byte => "SB",
+                StoreWidth::Half => "SH",
+                StoreWidth::Word => "SD"
+            },
+            Instruction::Jump(_, _) => "JUMP",
+            Instruction::Invalid => "INVALID"
+        }
+    }
+
+    /// 指令写入的目标寄存器
+    pub fn dest(&self) -> Option<usize> {
+        match self {
+            Instruction::Add(op) | Instruction::Sub(op) | Instruction::Mul(op) | Instruction::Div(op) => Some(op.target),
+            Instruction::Ld(reg1, _, _, _) => Some(*reg1),
+            _ => None
+        }
+    }
+
+    /// 访存指令的宽度
+    pub fn width(&self) -> Option<Width> {
+        match self {
+            Instruction::Ld(_, _, _, width) => Some(*width),
+            Instruction::Sd(_, _, _, width) => Some((*width).into()),
+            _ => None
+        }
+    }
+}
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Add(op) | Instruction::Sub(op) | Instruction::Mul(op) | Instruction::Div(op) => {
+                write!(f, "{},R{},R{},R{}", self.name(), op.target, op.operand1, op.operand2)
+            },
+            Instruction::Ld(reg1, reg2, imm, _) | Instruction::Sd(reg1, reg2, imm, _) => {
+                write!(f, "{},R{},R{},{}", self.name(), reg1, reg2, imm)
+            },
+            Instruction::Jump(reg1, reg2) => write!(f, "{},R{},R{}", self.name(), reg1, reg2),
+            Instruction::Invalid => write!(f, "{}", self.name())
+        }
+    }
+}
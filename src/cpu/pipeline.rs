@@ -0,0 +1,452 @@
+use std::{collections::VecDeque, io::Write, fmt, str::FromStr};
+use crate::trace::Trace;
+
+use super::{ Instruction, Cpu, ArchState, Memory, MemFault, Stats, IssueSlot, Retired, ResStationType, CycleEvents, format_regs };
+use super::tomasulo::{ exec_cycles, LOAD_CYCLES };
+
+/// 流水线中的旁路
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Forwarding {
+    /// EX/MEM 流水线寄存器到 EX 的旁路，运算结果可以被下一条指令直接使用
+    pub ex_mem: bool,
+    /// MEM/WB 流水线寄存器到 EX 的旁路，包括 load 读出的数据
+    pub mem_wb: bool,
+    /// 寄存器堆前半周期写、后半周期读，译码时可以读到同一周期写回的值
+    pub register_file: bool
+}
+
+impl Forwarding {
+    /// 没有任何旁路
+    pub fn none() -> Self {
+        Self{ ex_mem: false, mem_wb: false, register_file: false }
+    }
+}
+
+impl Default for Forwarding {
+    fn default() -> Self {
+        Self{ ex_mem: true, mem_wb: true, register_file: true }
+    }
+}
+
+impl FromStr for Forwarding {
+    type Err = String;
+
+    /// 解析 `full`、`none` 或者以逗号分隔的 `ex_mem`、`mem_wb`、`regfile`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "full" => return Ok(Self::default()),
+            "none" => return Ok(Self::none()),
+            _ => {}
+        }
+        let mut forwarding = Self::none();
+        for path in s.split(',') {
+            match path.trim() {
+                "ex_mem" => forwarding.ex_mem = true,
+                "mem_wb" => forwarding.mem_wb = true,
+                "regfile" => forwarding.register_file = true,
+                _ => return Err(format!("unknown forwarding path: {}", path))
+            }
+        }
+        Ok(forwarding)
+    }
+}
+
+/// 流水线寄存器中的一条指令
+#[derive(Debug, Clone, Copy)]
+pub struct PipeSlot {
+    /// 指令在程序中的序号
+    pc: usize,
+    inst: Instruction,
+    /// 在当前阶段还需要停留的周期数
+    left: usize,
+    /// 读取到的操作数
+    vj: i32,
+    vk: i32,
+    /// 运算结果或者 load 读出的数据
+    value: Option<i32>,
+    /// 访存地址
+    address: Option<u32>
+}
+
+impl PipeSlot {
+    fn new(pc: usize, inst: Instruction) -> Self {
+        Self{ pc, inst, left: 0, vj: 0, vk: 0, value: None, address: None }
+    }
+}
+
+impl fmt::Display for PipeSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.pc, self.inst)?;
+        if self.left > 0 {
+            write!(f, " left={}", self.left)?;
+        }
+        Ok(())
+    }
+}
+
+/// 源寄存器，第一个为 Vj，第二个为 Vk
+fn sources(inst: &Instruction) -> (Option<usize>, Option<usize>) {
+    match inst {
+        Instruction::Add(op) | Instruction::Sub(op) | Instruction::Mul(op) | Instruction::Div(op) => {
+            (Some(op.operand1), Some(op.operand2))
+        },
+        Instruction::Ld(_, reg2, _, _) => (Some(*reg2), None),
+        // store 的 Vj 为基址寄存器，Vk 为要写入内存的寄存器
+        Instruction::Sd(reg1, reg2, _, _) => (Some(*reg2), Some(*reg1)),
+        Instruction::Jump(r1, r2) => (Some(*r1), Some(*r2)),
+        Instruction::Invalid => (None, None)
+    }
+}
+
+/// 经典的五级顺序流水线（IF、ID、EX、MEM、WB），
+/// EX 阶段中每类运算有一个独立的功能单元，多周期的运算在功能单元中停留 `exec_cycles` 个周期，
+/// 其间其他功能单元上的指令可以继续发射与执行，因此指令可能乱序完成；
+/// 访存在 MEM 阶段停留 `LOAD_CYCLES` 个周期，多个功能单元同时完成时最老的指令先进入 MEM。
+/// 译码阶段检测数据冲突、功能单元的结构冲突以及乱序完成带来的 WAW 冲突，操作数通过可配置的旁路进入 EX
+pub struct PipelinedCpu<'a> {
+    /// 是否完成
+    done: bool,
+    /// 周期数
+    cycles: usize,
+    /// 寄存器文件
+    regs: [i32; 32],
+    /// 寄存器最后一次被写回的周期数
+    written_at: [Option<usize>; 32],
+    /// 指令队列
+    instruction_queue: VecDeque<Instruction>,
+    /// 除 EX 以外各阶段的流水线寄存器，依次为 IF、ID、MEM、WB
+    stages: [Option<PipeSlot>; 4],
+    /// EX 阶段的功能单元，与 `EX_UNITS` 一一对应
+    units: [Option<PipeSlot>; 4],
+    /// 旁路配置
+    forwarding: Forwarding,
+    /// 内存
+    memory: Memory,
+    /// 追踪文件
+    trace: &'a mut Trace,
+    /// 下一条取指的指令序号
+    pc: usize,
+    /// 本周期发生的事件
+    events: CycleEvents,
+    /// 统计信息
+    stats: Stats
+}
+
+const IF: usize = 0;
+const ID: usize = 1;
+const MEM: usize = 2;
+const WB: usize = 3;
+
+/// EX 阶段的功能单元，每类运算一个
+pub const EX_UNITS: [ResStationType; 4] = [ResStationType::AddSub, ResStationType::MulDiv, ResStationType::LoadStore, ResStationType::JUMP];
+
+/// 指令使用的功能单元
+fn unit_of(inst: &Instruction) -> usize {
+    let rs_type: ResStationType = (*inst).into();
+    EX_UNITS.iter().position(|unit| *unit == rs_type).unwrap()
+}
+
+impl<'a> Cpu for PipelinedCpu<'a> {
+    fn add_inst(&mut self, inst: Instruction) {
+        self.instruction_queue.push_back(inst);
+    }
+
+    fn run(&mut self) -> Stats {
+        while !self.done() {
+            self.step();
+        }
+        self.stats()
+    }
+
+    fn trace<S>(&mut self, s: S)
+        where S: Into<String>
+    {
+        let s: String = s.into();
+        writeln!(self.trace.file, "{}", s).unwrap();
+    }
+
+    fn write_memory(&mut self, addr: u32, val: i32) {
+        self.memory.write(addr, val);
+    }
+
+    fn set_regs(&mut self, index: usize, number: i32) {
+        self.regs[index] = number;
+    }
+
+    fn state(&self) -> ArchState {
+        ArchState::new(self.regs, self.memory.words())
+    }
+}
+
+impl<'a> PipelinedCpu<'a> {
+    pub fn new(trace: &'a mut Trace) -> Self {
+        let mut stats = Stats::new();
+        // EX 阶段中每类运算各有一个功能单元
+        for rs_type in EX_UNITS {
+            stats.add_units(rs_type.name(), 1);
+        }
+        Self {
+            done: false,
+            cycles: 0,
+            regs: [0i32; 32],
+            written_at: [None; 32],
+            instruction_queue: VecDeque::new(),
+            stages: [None; 4],
+            units: [None; 4],
+            forwarding: Forwarding::default(),
+            memory: Memory::init(),
+            trace,
+            pc: 0,
+            events: CycleEvents::default(),
+            stats
+        }
+    }
+
+    /// 设置旁路，默认所有旁路都打开
+    pub fn set_forwarding(&mut self, forwarding: Forwarding) {
+        self.forwarding = forwarding;
+    }
+
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    /// 获取当前的统计信息
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        stats.finish(self.cycles);
+        stats
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// 下一条将取指的指令序号
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// 已经写回的指令数
+    pub fn committed(&self) -> usize {
+        self.stats.committed
+    }
+
+    /// 上一周期发生的事件
+    pub fn last_events(&self) -> &CycleEvents {
+        &self.events
+    }
+
+    pub fn regs(&self) -> &[i32; 32] {
+        &self.regs
+    }
+
+    pub fn read_memory(&self, addr: u32) -> i32 {
+        self.memory.read(addr)
+    }
+
+    pub fn instruction_queue(&self) -> &VecDeque<Instruction> {
+        &self.instruction_queue
+    }
+
+    /// 各阶段中的指令及阶段名称，依次为 IF、ID、EX 的各个功能单元、MEM、WB
+    pub fn stages(&self) -> Vec<(String, Option<PipeSlot>)> {
+        let ex = EX_UNITS.iter().zip(self.units.iter()).map(|(unit, slot)| (format!("EX:{}", unit.name()), *slot));
+        [("IF", IF), ("ID", ID)].into_iter().map(|(name, stage)| (name.to_string(), self.stages[stage]))
+            .chain(ex)
+            .chain([("MEM", MEM), ("WB", WB)].into_iter().map(|(name, stage)| (name.to_string(), self.stages[stage])))
+            .collect()
+    }
+
+    /// 流水线中的所有指令
+    fn in_flight(&self) -> impl Iterator<Item = &PipeSlot> {
+        self.stages.iter().chain(self.units.iter()).flatten()
+    }
+
+    /// 运行一个周期，返回该周期内发生的事件，
+    /// `fetched` 为取指的指令，`issued` 为离开译码进入 EX 的指令，`committed` 为写回的指令
+    pub fn step(&mut self) -> CycleEvents {
+        self.events = CycleEvents::default();
+        self.cycles += 1;
+        // 从后往前推进各阶段，后面的阶段空出来之后前面的指令才能进入
+        self.advance();
+        self.write_back();
+        self.memory_access();
+        self.execute();
+        self.sample_stats();
+        self.done = (self.stats.halted.is_some() || self.instruction_queue.is_empty()) && self.in_flight().next().is_none();
+        self.events.cycle = self.cycles;
+        self.events.done = self.done;
+        self.events.clone()
+    }
+
+    /// WB：写回寄存器，指令完成
+    fn write_back(&mut self) {
+        let Some(slot) = self.stages[WB].take() else { return };
+        let mut retired = Retired{ pc: slot.pc, inst: slot.inst, reg_write: None, address: slot.address };
+        if let (Some(dest), Some(value)) = (slot.inst.dest(), slot.value) {
+            self.regs[dest] = value;
+            self.written_at[dest] = Some(self.cycles);
+            retired.reg_write = Some((dest, value));
+        }
+        self.stats.record_commit(&slot.inst);
+        self.events.committed.push(retired);
+        self.trace(format_regs(&self.regs));
+    }
+
+    /// MEM：访存指令在最后一个周期读写内存
+    fn memory_access(&mut self) {
+        let Some(slot) = &mut self.stages[MEM] else { return };
+        slot.left = slot.left.saturating_sub(1);
+        if slot.left > 0 {
+            return
+        }
+        let res = match slot.inst {
+            Instruction::Ld(_, _, _, width) => self.memory.load(slot.address.unwrap(), width).map(|val| slot.value = Some(val)),
+            Instruction::Sd(_, _, _, width) => self.memory.store(slot.address.unwrap(), width.into(), slot.vk),
+            _ => Ok(())
+        };
+        if let Err(fault) = res {
+            let pc = slot.pc;
+            self.raise(pc, fault);
+        }
+    }
+
+    /// 访存异常：停止取指与发射。更晚的指令不会越过 MEM 中的访存指令，都还没有修改状态，直接丢弃；
+    /// 更早的多周期运算可能还在 EX 中，等它们写回之后停止运行，因此异常是精确的
+    fn raise(&mut self, pc: usize, fault: MemFault) {
+        self.stats.halted = Some(format!("pc {}: {}", pc, fault));
+        self.stages[MEM] = None;
+        for slot in self.stages.iter_mut().chain(self.units.iter_mut()) {
+            if slot.is_some_and(|slot| slot.pc > pc) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// EX：各功能单元独立执行，运算在最后一个周期得到结果，访存指令计算地址
+    fn execute(&mut self) {
+        for slot in self.units.iter_mut().flatten() {
+            if slot.left == 0 {
+                // 已经完成，等待进入 MEM
+                continue
+            }
+            slot.left -= 1;
+            if slot.left > 0 {
+                continue
+            }
+            let (vj, vk) = (slot.vj, slot.vk);
+            match slot.inst {
+                Instruction::Add(_) => slot.value = Some(vj.checked_add(vk).unwrap_or_default()),
+                Instruction::Sub(_) => slot.value = Some(vj.checked_sub(vk).unwrap_or_default()),
+                Instruction::Mul(_) => slot.value = Some(vj.checked_mul(vk).unwrap_or_default()),
+                Instruction::Div(_) => slot.value = Some(vj.checked_div(vk).unwrap_or_default()),
+                Instruction::Ld(_, _, imm, _) | Instruction::Sd(_, _, imm, _) => slot.address = Some((vj + (imm as i32)) as u32),
+                _ => {}
+            }
+        }
+    }
+
+    /// 在周期开始时将上一周期完成的指令推进到下一阶段
+    fn advance(&mut self) {
+        // MEM -> WB
+        if self.stages[MEM].is_some_and(|slot| slot.left == 0) {
+            self.stages[WB] = self.stages[MEM].take();
+        }
+        // EX -> MEM，多个功能单元同时完成时最老的指令先进入，其余的留在功能单元中
+        let finished = (0..self.units.len())
+            .filter(|unit| self.units[*unit].is_some_and(|slot| slot.left == 0))
+            .min_by_key(|unit| self.units[*unit].unwrap().pc);
+        if let (None, Some(unit)) = (self.stages[MEM], finished) {
+            let mut slot = self.units[unit].take().unwrap();
+            // 拆分的非对齐访存需要两次访存的时间
+            slot.left = match slot.inst.width() {
+                Some(width) => {
+                    let write = matches!(slot.inst, Instruction::Sd(..));
+                    LOAD_CYCLES * self.memory.check(slot.address.unwrap(), width, write).unwrap_or(1)
+                },
+                _ => 1
+            };
+            self.stages[MEM] = Some(slot);
+        }
+        // ID -> EX
+        let slot = match self.stages[ID] {
+            None => IssueSlot::QueueEmpty,
+            Some(slot) => match self.units[unit_of(&slot.inst)] {
+                // 功能单元被多周期运算占用，结构冲突
+                Some(_) => IssueSlot::UnitBusy(slot.inst.into()),
+                // 更早的指令还在其他功能单元中且写同一寄存器，乱序完成会导致 WAW 冲突
+                None if slot.inst.dest().is_some_and(|dest| self.units.iter().flatten().any(|busy| busy.inst.dest() == Some(dest))) => {
+                    IssueSlot::WawHazard
+                },
+                None => self.issue(slot)
+            }
+        };
+        self.events.issue_slots.push(slot);
+        self.stats.record_issue(slot, 1);
+        // IF -> ID
+        if self.stages[ID].is_none() {
+            self.stages[ID] = self.stages[IF].take();
+        }
+        // 取指，发生异常之后不再取指
+        if self.stages[IF].is_none() && self.stats.halted.is_none() {
+            if let Some(inst) = self.instruction_queue.pop_front() {
+                self.stages[IF] = Some(PipeSlot::new(self.pc, inst));
+                self.events.fetched.push((self.pc, inst));
+                self.pc += 1;
+            }
+        }
+    }
+
+    /// 读取寄存器 reg 在本周期进入 EX 时的值，存在数据冲突时返回 None
+    fn operand(&self, reg: usize) -> Option<i32> {
+        // 最近的、仍在流水线中要写该寄存器的指令，EX 中的指令还没有可以旁路的结果
+        let in_ex = self.units.iter().flatten().map(|slot| (None, slot));
+        let producer = [MEM, WB].into_iter()
+            .filter_map(|stage| self.stages[stage].as_ref().map(|slot| (Some(stage), slot)))
+            .chain(in_ex)
+            .filter(|(_, slot)| slot.inst.dest() == Some(reg))
+            .max_by_key(|(_, slot)| slot.pc);
+        match producer {
+            Some((Some(MEM), slot)) if self.forwarding.ex_mem && !matches!(slot.inst, Instruction::Ld(..)) => slot.value,
+            Some((Some(WB), slot)) if self.forwarding.mem_wb => slot.value,
+            Some(_) => None,
+            // 上一周期译码时刚写回的寄存器只有在寄存器堆先写后读时才能读到
+            None if self.written_at[reg] == Some(self.cycles - 1) && !self.forwarding.register_file => None,
+            None => Some(self.regs[reg])
+        }
+    }
+
+    /// 译码完成的指令在没有数据冲突时进入 EX，本周期开始执行
+    fn issue(&mut self, mut slot: PipeSlot) -> IssueSlot {
+        let (rj, rk) = sources(&slot.inst);
+        let vj = rj.map(|reg| self.operand(reg));
+        let vk = rk.map(|reg| self.operand(reg));
+        if vj == Some(None) || vk == Some(None) {
+            self.stats.stalls.operand_wait += 1;
+            return IssueSlot::DataHazard
+        }
+        slot.vj = vj.flatten().unwrap_or_default();
+        slot.vk = vk.flatten().unwrap_or_default();
+        slot.left = match slot.inst {
+            // 访存指令在 EX 只计算地址
+            Instruction::Ld(..) | Instruction::Sd(..) => 1,
+            inst => exec_cycles(&inst)
+        };
+        self.stages[ID] = None;
+        self.units[unit_of(&slot.inst)] = Some(slot);
+        self.events.issued.push((slot.pc, slot.inst));
+        IssueSlot::Issued
+    }
+
+    /// 每周期采样功能单元与流水线的占用情况
+    fn sample_stats(&mut self) {
+        let busy = self.in_flight().count();
+        self.stats.sample_occupancy(0, busy);
+        let mem = self.stages[MEM].is_some_and(|slot| matches!(slot.inst, Instruction::Ld(..) | Instruction::Sd(..)));
+        for (rs_type, slot) in EX_UNITS.into_iter().zip(self.units) {
+            let busy = slot.is_some() || (rs_type == ResStationType::LoadStore && mem);
+            self.stats.record_unit_busy(rs_type.name(), busy as usize);
+        }
+    }
+}
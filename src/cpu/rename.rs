@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+use serde::{ Serialize, Deserialize };
+
+/// 显式的物理寄存器堆（R10K 风格），
+/// 包括寄存器别名表（RAT）、提交时的别名表以及空闲列表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhysRegFile {
+    /// 物理寄存器的值
+    values: Vec<i32>,
+    /// 物理寄存器的值是否已经写回
+    ready: Vec<bool>,
+    /// 推测的别名表，发射时更新
+    rat: [usize; 32],
+    /// 提交的别名表，提交时更新
+    retire_rat: [usize; 32],
+    /// 空闲的物理寄存器
+    free_list: VecDeque<usize>
+}
+
+impl PhysRegFile {
+    /// 创建 count 个物理寄存器，逻辑寄存器 i 初始映射到物理寄存器 i
+    pub(crate) fn new(count: usize, regs: &[i32; 32]) -> Self {
+        assert!(count > 32, "need more physical registers than architectural registers");
+        let mut values = vec![0; count];
+        values[..32].copy_from_slice(regs);
+        Self {
+            values,
+            ready: vec![true; count],
+            rat: std::array::from_fn(|i| i),
+            retire_rat: std::array::from_fn(|i| i),
+            free_list: (32..count).collect()
+        }
+    }
+
+    /// 逻辑寄存器当前映射到的物理寄存器
+    pub fn mapping(&self, reg: usize) -> usize {
+        self.rat[reg]
+    }
+
+    /// 逻辑寄存器已提交的映射
+    pub fn retired_mapping(&self, reg: usize) -> usize {
+        self.retire_rat[reg]
+    }
+
+    pub fn value(&self, phys: usize) -> i32 {
+        self.values[phys]
+    }
+
+    pub fn ready(&self, phys: usize) -> bool {
+        self.ready[phys]
+    }
+
+    /// 空闲物理寄存器的个数
+    pub fn free_count(&self) -> usize {
+        self.free_list.len()
+    }
+
+    /// 读取逻辑寄存器的操作数，值尚未写回时返回 Err(物理寄存器编号)
+    pub(crate) fn read(&self, reg: usize) -> Result<i32, usize> {
+        let phys = self.rat[reg];
+        if self.ready[phys] { Ok(self.values[phys]) } else { Err(phys) }
+    }
+
+    /// 为目标寄存器分配新的物理寄存器，返回 (新的物理寄存器, 之前的映射)
+    pub(crate) fn rename(&mut self, reg: usize) -> Option<(usize, usize)> {
+        let phys = self.free_list.pop_front()?;
+        self.ready[phys] = false;
+        let prev = std::mem::replace(&mut self.rat[reg], phys);
+        Some((phys, prev))
+    }
+
+    /// CDB 写回结果
+    pub(crate) fn write(&mut self, phys: usize, val: i32) {
+        self.values[phys] = val;
+        self.ready[phys] = true;
+    }
+
+    /// 提交时更新提交的别名表，并释放之前的映射
+    pub(crate) fn retire(&mut self, reg: usize, phys: usize, prev: usize) {
+        self.retire_rat[reg] = phys;
+        self.free_list.push_back(prev);
+    }
+
+    /// 直接设置逻辑寄存器已提交的值，仅用于初始化
+    pub(crate) fn set(&mut self, reg: usize, val: i32) {
+        self.values[self.retire_rat[reg]] = val;
+    }
+}
@@ -0,0 +1,104 @@
+use std::str::FromStr;
+use crate::trace::Trace;
+
+use super::{ Cpu, SingleCycleCpu, TomasuloCpu, SamplingStats };
+
+/// 采样模拟的配置：先按单周期 CPU 的语义快进 `fast_forward` 条指令，
+/// 之后每个采样区间将体系结构状态交给 TomasuloCpu，详细模拟 `warmup` 条指令预热、
+/// 再测量 `detail` 条指令的 IPC；设置了 `period` 时每隔 `period` 条指令采样一次（SMARTS），
+/// 否则只采样一次
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sampling {
+    pub fast_forward: usize,
+    pub warmup: usize,
+    pub detail: usize,
+    pub period: Option<usize>
+}
+
+impl FromStr for Sampling {
+    type Err = String;
+
+    /// 解析 `fast_forward,warmup,detail[,period]`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let items = s.split(',')
+            .map(|item| item.trim().parse::<usize>().map_err(|_| format!("invalid number: {}", item)))
+            .collect::<Result<Vec<usize>, String>>()?;
+        let (fast_forward, warmup, detail, period) = match items.as_slice() {
+            [fast_forward, warmup, detail] => (*fast_forward, *warmup, *detail, None),
+            [fast_forward, warmup, detail, period] => (*fast_forward, *warmup, *detail, Some(*period)),
+            _ => return Err(format!("expect fast_forward,warmup,detail[,period]: {}", s))
+        };
+        if detail == 0 {
+            return Err("detail must be positive".to_string())
+        }
+        if period.is_some_and(|period| period < warmup + detail) {
+            return Err(format!("period must be at least warmup + detail: {}", s))
+        }
+        Ok(Self{ fast_forward, warmup, detail, period })
+    }
+}
+
+impl Sampling {
+    /// 运行采样模拟：functional 为已经读入程序与数据的单周期 CPU，负责快进，
+    /// 每个采样区间用 configure 配置一个新的 TomasuloCpu。运行结束后 functional 执行完了全部指令，
+    /// 其体系结构状态即为程序的最终状态
+    pub fn run<F>(&self, functional: &mut SingleCycleCpu, trace: &mut Trace, configure: F) -> SamplingStats
+        where F: Fn(&mut TomasuloCpu)
+    {
+        let mut fast_forwarded = Self::fast_forward(functional, self.fast_forward);
+        let mut detailed = 0;
+        let mut samples = vec![];
+        let length = self.warmup + self.detail;
+        while functional.instruction_queue.len() >= length {
+            match self.sample(functional, trace, &configure) {
+                Some(sample) => samples.push(sample),
+                None => break
+            }
+            // 采样区间的指令同样由单周期 CPU 执行，保持体系结构状态一致
+            detailed += Self::fast_forward(functional, length);
+            match self.period {
+                Some(period) => fast_forwarded += Self::fast_forward(functional, period - length),
+                None => break
+            }
+        }
+        fast_forwarded += Self::fast_forward(functional, usize::MAX);
+        SamplingStats::new(fast_forwarded, detailed, samples)
+    }
+
+    /// 功能模拟最多 count 条指令，返回实际执行的条数
+    fn fast_forward(functional: &mut SingleCycleCpu, count: usize) -> usize {
+        (0..count).take_while(|_| functional.step().is_some()).count()
+    }
+
+    /// 从 functional 的当前状态开始详细模拟一个采样区间，返回测量的 (指令数, 周期数)，
+    /// 详细模拟被强制停止时返回 None
+    fn sample<F>(&self, functional: &SingleCycleCpu, trace: &mut Trace, configure: &F) -> Option<(usize, usize)>
+        where F: Fn(&mut TomasuloCpu)
+    {
+        let mut cpu = TomasuloCpu::new(trace);
+        configure(&mut cpu);
+        for (index, val) in functional.regs().iter().enumerate() {
+            cpu.set_regs(index, *val);
+        }
+        for (addr, val) in functional.state().memory {
+            cpu.write_memory(addr, val);
+        }
+        cpu.set_pc(functional.pc());
+        // 给出之后的全部指令，使测量区间结束时流水线仍然是满的
+        for inst in functional.instruction_queue.iter() {
+            cpu.add_inst(*inst);
+        }
+        let mut start = (self.warmup == 0).then_some((0, 0));
+        while cpu.committed() < self.warmup + self.detail {
+            if cpu.done() {
+                return None
+            }
+            cpu.step();
+            if start.is_none() && cpu.committed() >= self.warmup {
+                start = Some((cpu.committed(), cpu.cycles()));
+            }
+        }
+        let (committed, cycles) = start.unwrap();
+        Some((cpu.committed() - committed, cpu.cycles() - cycles))
+    }
+}
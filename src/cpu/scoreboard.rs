@@ -0,0 +1,466 @@
+use std::{collections::VecDeque, io::Write, fmt};
+use crate::trace::Trace;
+
+use super::{ Instruction, Cpu, ArchState, Memory, MemFault, Stats, IssueSlot, RsState, Retired, ResStationType, CycleEvents, format_regs, opt };
+use super::tomasulo::exec_cycles;
+
+/// 功能单元中的指令所处的阶段
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    /// 已发射，等待读取操作数
+    ReadOperands,
+    /// 正在执行
+    Execute,
+    /// 执行完毕，等待写结果
+    WriteResult
+}
+
+/// 计分板中的功能单元状态
+#[derive(Debug)]
+pub struct FunctionalUnit {
+    unit_type: ResStationType,
+    busy: bool,
+    stage: Stage,
+    inst: Option<Instruction>,
+    /// 指令在程序中的序号
+    pc: usize,
+    /// Fi，目标寄存器
+    fi: Option<usize>,
+    /// Fj，源寄存器
+    fj: Option<usize>,
+    /// Fk，源寄存器
+    fk: Option<usize>,
+    /// Qj，产生 Fj 的功能单元
+    qj: Option<usize>,
+    /// Qk，产生 Fk 的功能单元
+    qk: Option<usize>,
+    /// Rj，Fj 已经就绪且尚未读取
+    rj: bool,
+    /// Rk，Fk 已经就绪且尚未读取
+    rk: bool,
+    /// 读取到的操作数
+    vj: i32,
+    vk: i32,
+    /// 剩余的执行周期数
+    cycles: usize,
+    /// 发射或者最后一个操作数写回时的周期数，读操作数至少在下一周期
+    woken_at: usize
+}
+
+impl FunctionalUnit {
+    fn init(unit_type: ResStationType) -> Self {
+        Self {
+            unit_type,
+            busy: false,
+            stage: Stage::ReadOperands,
+            inst: None,
+            pc: 0,
+            fi: None,
+            fj: None,
+            fk: None,
+            qj: None,
+            qk: None,
+            rj: false,
+            rk: false,
+            vj: 0,
+            vk: 0,
+            cycles: 0,
+            woken_at: 0
+        }
+    }
+}
+
+impl fmt::Display for FunctionalUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.busy {
+            return write!(f, "{:<10} idle", self.unit_type.name())
+        }
+        write!(
+            f, "{:<10}{:<14}{:<16}{:>4}{:>4}{:>4}{:>4}{:>4}{:>7}{:>7}{:>5}",
+            self.unit_type.name(), format!("{:?}", self.stage), opt(&self.inst),
+            opt(&self.fi), opt(&self.fj), opt(&self.fk), opt(&self.qj), opt(&self.qk),
+            self.rj, self.rk, self.cycles
+        )
+    }
+}
+
+/// 计分板（CDC 6600）CPU，
+/// 每条指令依次经过发射、读操作数、执行、写结果四个阶段，
+/// 发射时检查结构冲突与 WAW，读操作数时等待 RAW，写结果时检查 WAR
+pub struct ScoreboardCpu<'a> {
+    /// 是否完成
+    done: bool,
+    /// 周期数
+    cycles: usize,
+    /// 寄存器文件
+    regs: [i32; 32],
+    /// 寄存器结果状态，记录将写该寄存器的功能单元
+    result: [Option<usize>; 32],
+    /// 指令队列
+    instruction_queue: VecDeque<Instruction>,
+    /// 功能单元
+    units: Vec<FunctionalUnit>,
+    /// 内存
+    memory: Memory,
+    /// 追踪文件
+    trace: &'a mut Trace,
+    /// 下一条发射的指令序号
+    pc: usize,
+    /// 本周期发生的事件
+    events: CycleEvents,
+    /// 统计信息
+    stats: Stats
+}
+
+impl<'a> Cpu for ScoreboardCpu<'a> {
+    fn add_inst(&mut self, inst: Instruction) {
+        self.instruction_queue.push_back(inst);
+    }
+
+    fn run(&mut self) -> Stats {
+        while !self.done() {
+            self.step();
+        }
+        self.stats()
+    }
+
+    fn trace<S>(&mut self, s: S)
+        where S: Into<String>
+    {
+        let s: String = s.into();
+        writeln!(self.trace.file, "{}", s).unwrap();
+    }
+
+    fn write_memory(&mut self, addr: u32, val: i32) {
+        self.memory.write(addr, val);
+    }
+
+    fn set_regs(&mut self, index: usize, number: i32) {
+        self.regs[index] = number;
+    }
+
+    fn state(&self) -> ArchState {
+        ArchState::new(self.regs, self.memory.words())
+    }
+}
+
+impl<'a> ScoreboardCpu<'a> {
+    /// 功能单元的个数与 `TomasuloCpu::new` 的执行单元相同，便于比较
+    pub fn new(trace: &'a mut Trace) -> Self {
+        let mut cpu = Self::new_empty(trace);
+        cpu.add_unit(ResStationType::AddSub, 3);
+        cpu.add_unit(ResStationType::MulDiv, 2);
+        cpu.add_unit(ResStationType::LoadStore, 3);
+        cpu.add_unit(ResStationType::JUMP, 3);
+        cpu
+    }
+
+    /// 创建一个没有功能单元的 CPU，需要通过 `add_unit` 自行配置
+    pub fn new_empty(trace: &'a mut Trace) -> Self {
+        Self {
+            done: false,
+            cycles: 0,
+            regs: [0i32; 32],
+            result: [None; 32],
+            instruction_queue: VecDeque::new(),
+            units: vec![],
+            memory: Memory::init(),
+            trace,
+            pc: 0,
+            events: CycleEvents::default(),
+            stats: Stats::new()
+        }
+    }
+
+    /// 添加功能单元
+    pub fn add_unit(&mut self, unit_type: ResStationType, count: usize) {
+        for _ in 0..count {
+            self.units.push(FunctionalUnit::init(unit_type));
+        }
+        self.stats.add_units(unit_type.name(), count);
+    }
+
+    pub fn done(&self) -> bool {
+        self.done
+    }
+
+    /// 获取当前的统计信息
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.clone();
+        stats.finish(self.cycles);
+        stats
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// 下一条将发射的指令序号
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// 已经完成的指令数
+    pub fn committed(&self) -> usize {
+        self.stats.committed
+    }
+
+    /// 上一周期发生的事件
+    pub fn last_events(&self) -> &CycleEvents {
+        &self.events
+    }
+
+    pub fn regs(&self) -> &[i32; 32] {
+        &self.regs
+    }
+
+    pub fn read_memory(&self, addr: u32) -> i32 {
+        self.memory.read(addr)
+    }
+
+    pub fn instruction_queue(&self) -> &VecDeque<Instruction> {
+        &self.instruction_queue
+    }
+
+    pub fn units(&self) -> &[FunctionalUnit] {
+        &self.units
+    }
+
+    /// 寄存器结果状态，即将写该寄存器的功能单元
+    pub fn result_status(&self) -> &[Option<usize>; 32] {
+        &self.result
+    }
+
+    /// 运行一个周期，返回该周期内发生的事件，
+    /// 由于没有 CDB，`written_back` 始终为空，`dispatched` 为读取操作数并开始执行的指令，
+    /// `committed` 为写结果的指令
+    pub fn step(&mut self) -> CycleEvents {
+        self.events = CycleEvents::default();
+        self.cycles += 1;
+        // 逆序处理各阶段，保证每条指令在每个阶段至少停留一个周期
+        self.write_result();
+        self.execute();
+        self.read_operands();
+        let slot = self.issue();
+        self.events.issue_slots.push(slot);
+        self.stats.record_issue(slot, 1);
+        self.sample_stats();
+        self.done = self.stats.halted.is_some()
+            || (self.instruction_queue.is_empty() && self.units.iter().all(|unit| !unit.busy));
+        self.events.cycle = self.cycles;
+        self.events.done = self.done;
+        self.events.clone()
+    }
+
+    /// 发射，每周期按程序顺序最多发射一条指令
+    fn issue(&mut self) -> IssueSlot {
+        let Some(inst) = self.instruction_queue.front().copied() else {
+            return IssueSlot::QueueEmpty
+        };
+        let unit_type: ResStationType = inst.into();
+        let Some(u) = self.units.iter().position(|unit| !unit.busy && unit.unit_type == unit_type) else {
+            return IssueSlot::UnitBusy(unit_type)
+        };
+        let (fj, fk) = match inst {
+            Instruction::Add(op) | Instruction::Sub(op) | Instruction::Mul(op) | Instruction::Div(op) => {
+                (Some(op.operand1), Some(op.operand2))
+            },
+            Instruction::Ld(_, reg2, _, _) => (Some(reg2), None),
+            // store 的 Fj 为基址寄存器，Fk 为要写入内存的寄存器
+            Instruction::Sd(reg1, reg2, _, _) => (Some(reg2), Some(reg1)),
+            Instruction::Jump(r1, r2) => (Some(r1), Some(r2)),
+            Instruction::Invalid => panic!("[Error] Invalid instruction")
+        };
+        let fi = inst.dest();
+        // 已经有功能单元要写同一个寄存器，存在 WAW 冲突
+        if fi.is_some_and(|fi| self.result[fi].is_some()) {
+            return IssueSlot::WawHazard
+        }
+        self.instruction_queue.pop_front();
+        let qj = fj.and_then(|fj| self.result[fj]);
+        let qk = fk.and_then(|fk| self.result[fk]);
+        let unit = &mut self.units[u];
+        unit.busy = true;
+        unit.stage = Stage::ReadOperands;
+        unit.inst = Some(inst);
+        unit.pc = self.pc;
+        unit.fi = fi;
+        unit.fj = fj;
+        unit.fk = fk;
+        unit.qj = qj;
+        unit.qk = qk;
+        unit.rj = fj.is_some() && qj.is_none();
+        unit.rk = fk.is_some() && qk.is_none();
+        unit.woken_at = self.cycles;
+        if let Some(fi) = fi {
+            self.result[fi] = Some(u);
+        }
+        self.events.issued.push((self.pc, inst));
+        self.pc += 1;
+        IssueSlot::Issued
+    }
+
+    /// 读操作数，两个操作数都就绪后读取寄存器并开始执行
+    fn read_operands(&mut self) {
+        for u in 0..self.units.len() {
+            let unit = &self.units[u];
+            if !unit.busy || unit.stage != Stage::ReadOperands || unit.woken_at >= self.cycles
+                || unit.qj.is_some() || unit.qk.is_some() {
+                continue;
+            }
+            let vj = unit.fj.map_or(0, |fj| self.regs[fj]);
+            let vk = unit.fk.map_or(0, |fk| self.regs[fk]);
+            let inst = unit.inst.unwrap();
+            let unit = &mut self.units[u];
+            unit.vj = vj;
+            unit.vk = vk;
+            unit.rj = false;
+            unit.rk = false;
+            unit.stage = Stage::Execute;
+            // 拆分的非对齐访存需要两次访存的时间
+            let accesses = match inst {
+                Instruction::Ld(_, _, imm, _) | Instruction::Sd(_, _, imm, _) => {
+                    let write = matches!(inst, Instruction::Sd(..));
+                    self.memory.check((vj + (imm as i32)) as u32, inst.width().unwrap(), write).unwrap_or(1)
+                },
+                _ => 1
+            };
+            let unit = &mut self.units[u];
+            unit.cycles = exec_cycles(&inst) * accesses;
+            self.events.dispatched.push((unit.pc, inst));
+        }
+    }
+
+    /// 执行，执行完毕后进入写结果阶段
+    fn execute(&mut self) {
+        for unit in self.units.iter_mut() {
+            if unit.busy && unit.stage == Stage::Execute {
+                unit.cycles = unit.cycles.saturating_sub(1);
+                if unit.cycles == 0 {
+                    unit.stage = Stage::WriteResult;
+                }
+            }
+        }
+    }
+
+    /// 写结果阶段是否存在 WAR 冲突，即还有功能单元没有读取即将被覆盖的寄存器
+    fn war_hazard(&self, u: usize) -> bool {
+        let Some(fi) = self.units[u].fi else { return false };
+        self.units.iter().enumerate().any(|(f, unit)| {
+            f != u && unit.busy && ((unit.fj == Some(fi) && unit.rj) || (unit.fk == Some(fi) && unit.rk))
+        })
+    }
+
+    /// 访存指令按程序顺序写结果，保证 load 与 store 之间的内存依赖
+    fn older_memory_op(&self, u: usize) -> bool {
+        let unit = &self.units[u];
+        unit.unit_type == ResStationType::LoadStore && self.units.iter().any(|other| {
+            other.busy && other.unit_type == ResStationType::LoadStore && other.pc < unit.pc
+        })
+    }
+
+    /// 写结果，没有 WAR 冲突时写回寄存器并释放功能单元
+    fn write_result(&mut self) {
+        let mut ready: Vec<usize> = (0..self.units.len())
+            .filter(|u| self.units[*u].busy && self.units[*u].stage == Stage::WriteResult)
+            .collect();
+        ready.sort_by_key(|u| self.units[*u].pc);
+        for u in ready {
+            if self.older_memory_op(u) {
+                continue;
+            }
+            if self.war_hazard(u) {
+                self.stats.stalls.war_hazard += 1;
+                continue;
+            }
+            self.finish(u);
+        }
+    }
+
+    /// 访存异常：停止执行，计分板乱序写回，异常是不精确的
+    fn raise(&mut self, pc: usize, fault: MemFault) {
+        self.stats.halted = Some(format!("pc {}: {}", pc, fault));
+    }
+
+    /// 计算功能单元 u 的结果并写回
+    fn finish(&mut self, u: usize) {
+        let unit = &self.units[u];
+        let inst = unit.inst.unwrap();
+        let mut retired = Retired{ pc: unit.pc, inst, reg_write: None, address: None };
+        let res = match inst {
+            Instruction::Add(_) => unit.vj.checked_add(unit.vk).unwrap_or_default(),
+            Instruction::Sub(_) => unit.vj.checked_sub(unit.vk).unwrap_or_default(),
+            Instruction::Mul(_) => unit.vj.checked_mul(unit.vk).unwrap_or_default(),
+            Instruction::Div(_) => unit.vj.checked_div(unit.vk).unwrap_or_default(),
+            Instruction::Ld(_, _, imm, width) => {
+                let addr = (unit.vj + (imm as i32)) as u32;
+                retired.address = Some(addr);
+                match self.memory.load(addr, width) {
+                    Ok(val) => val,
+                    Err(fault) => return self.raise(retired.pc, fault)
+                }
+            },
+            Instruction::Sd(_, _, imm, width) => {
+                let addr = (unit.vj + (imm as i32)) as u32;
+                retired.address = Some(addr);
+                if let Err(fault) = self.memory.store(addr, width.into(), unit.vk) {
+                    return self.raise(retired.pc, fault)
+                }
+                0
+            },
+            _ => 0
+        };
+        if let Some(fi) = self.units[u].fi {
+            self.regs[fi] = res;
+            self.result[fi] = None;
+            retired.reg_write = Some((fi, res));
+        }
+        // 唤醒等待该功能单元结果的功能单元
+        for unit in self.units.iter_mut() {
+            if unit.qj == Some(u) {
+                unit.qj = None;
+                unit.rj = true;
+                unit.woken_at = self.cycles;
+            }
+            if unit.qk == Some(u) {
+                unit.qk = None;
+                unit.rk = true;
+                unit.woken_at = self.cycles;
+            }
+        }
+        self.units[u].busy = false;
+        self.stats.record_commit(&inst);
+        self.events.committed.push(retired);
+        self.trace(format_regs(&self.regs));
+    }
+
+    /// 每周期采样功能单元的占用情况
+    fn sample_stats(&mut self) {
+        let busy = self.units.iter().filter(|unit| unit.busy).count();
+        self.stats.sample_occupancy(0, busy);
+        for unit_type in [ResStationType::AddSub, ResStationType::MulDiv, ResStationType::LoadStore, ResStationType::JUMP] {
+            let busy = self.units.iter().filter(|unit| unit.busy && unit.unit_type == unit_type).count();
+            self.stats.record_unit_busy(unit_type.name(), busy);
+        }
+        for u in 0..self.units.len() {
+            if let Some(state) = self.unit_state(u) {
+                self.stats.record_rs_state(self.units[u].unit_type, state);
+            }
+        }
+    }
+
+    /// 功能单元所处的状态，沿用保留站的分类，等待写结果记为 `WaitingCdb`
+    fn unit_state(&self, u: usize) -> Option<RsState> {
+        let unit = &self.units[u];
+        if !unit.busy {
+            return None
+        }
+        match unit.stage {
+            Stage::ReadOperands if unit.qj.is_some() => Some(RsState::WaitingQj),
+            Stage::ReadOperands if unit.qk.is_some() => Some(RsState::WaitingQk),
+            Stage::ReadOperands => Some(RsState::Latched),
+            Stage::Execute => Some(RsState::Executing),
+            Stage::WriteResult => Some(RsState::WaitingCdb)
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use std::{fmt, str::FromStr};
+use serde::{ Serialize, Deserialize };
+
+/// 体系结构状态：寄存器以及写过的内存字，用于导出运行结果并与期望结果比较
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArchState {
+    pub regs: [i32; 32],
+    /// key: 按字对齐的地址 value: 该地址上的字
+    pub memory: BTreeMap<u32, i32>
+}
+
+impl ArchState {
+    pub fn new(regs: [i32; 32], memory: BTreeMap<u32, i32>) -> Self {
+        Self{ regs, memory }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|err| format!("err: {}", err))
+    }
+
+    /// 写入状态文件，`.json` 结尾的文件为 JSON 格式，否则为文本格式
+    pub fn save(&self, filename: &str) -> Result<(), String> {
+        let content = if filename.ends_with(".json") { self.to_json() } else { self.to_string() };
+        std::fs::write(filename, content).map_err(|err| format!("err: {}", err))
+    }
+
+    /// 命令行参数中有 `--dump file` 时写入状态文件
+    pub fn dump(&self, args: &[String]) -> Result<(), String> {
+        match args.iter().position(|arg| arg == "--dump") {
+            Some(pos) => self.save(args.get(pos + 1).ok_or("missing dump file".to_string())?),
+            None => Ok(())
+        }
+    }
+
+    /// 与期望的状态比较，列出所有不同的寄存器与内存字，没有写过的内存视为 0
+    pub fn diff(&self, expected: &ArchState) -> Vec<StateDiff> {
+        let regs = (0..self.regs.len())
+            .filter(|reg| self.regs[*reg] != expected.regs[*reg])
+            .map(|reg| StateDiff::Reg(reg, expected.regs[reg], self.regs[reg]));
+        let mut addrs: Vec<u32> = self.memory.keys().chain(expected.memory.keys()).copied().collect();
+        addrs.sort();
+        addrs.dedup();
+        let memory = addrs.into_iter().filter_map(|addr| {
+            let expected = expected.memory.get(&addr).copied().unwrap_or_default();
+            let actual = self.memory.get(&addr).copied().unwrap_or_default();
+            (expected != actual).then_some(StateDiff::Mem(addr, expected, actual))
+        });
+        regs.chain(memory).collect()
+    }
+}
+
+/// 文本格式：寄存器为 `R<n>: value`，内存与 data.txt 相同为 `addr: value`，`#` 开头的行为注释
+impl fmt::Display for ArchState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# registers")?;
+        for (reg, val) in self.regs.iter().enumerate() {
+            writeln!(f, "R{}: {}", reg, val)?;
+        }
+        writeln!(f, "# memory")?;
+        for (addr, val) in &self.memory {
+            writeln!(f, "{}: {}", addr, val)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ArchState {
+    type Err = String;
+
+    /// 没有出现的寄存器为 0
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut state = Self::default();
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (key, val) = line.split_once(':').ok_or(format!("expect key: value: {}", line))?;
+            let val: i32 = val.trim().parse().map_err(|_| format!("invalid value: {}", line))?;
+            match key.trim().strip_prefix('R') {
+                Some(reg) => {
+                    let reg: usize = reg.parse().map_err(|_| format!("invalid register: {}", line))?;
+                    *state.regs.get_mut(reg).ok_or(format!("invalid register: {}", line))? = val;
+                },
+                None => {
+                    let addr: u32 = key.trim().parse().map_err(|_| format!("invalid address: {}", line))?;
+                    state.memory.insert(addr, val);
+                }
+            }
+        }
+        Ok(state)
+    }
+}
+
+/// 两个状态之间的一处不同，(位置, 期望值, 实际值)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StateDiff {
+    Reg(usize, i32, i32),
+    Mem(u32, i32, i32)
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateDiff::Reg(reg, expected, actual) => write!(f, "R{}: expected {}, actual {}", reg, expected, actual),
+            StateDiff::Mem(addr, expected, actual) => write!(f, "mem[{}]: expected {}, actual {}", addr, expected, actual)
+        }
+    }
+}
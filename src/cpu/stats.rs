@@ -0,0 +1,571 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{ Serialize, Deserialize };
+
+use super::{ Instruction, ResStationType };
+
+/// 每个发射槽在一个周期内的结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IssueSlot {
+    /// 成功发射
+    Issued,
+    /// ROB 已满
+    RobFull,
+    /// 对应类型的保留站已满
+    RsFull(ResStationType),
+    /// 计分板与顺序流水线中对应类型的功能单元都被占用，结构冲突
+    UnitBusy(ResStationType),
+    /// 没有空闲的物理寄存器
+    FreeListEmpty,
+    /// 计分板中已有指令要写同一个目标寄存器
+    WawHazard,
+    /// 顺序流水线中操作数尚未产生且无法通过旁路获得
+    DataHazard,
+    /// 指令队列为空
+    QueueEmpty,
+    /// 取指带宽不足或者指令仍在译码
+    FetchStarved,
+    /// 前端因跳转重定向而无法提供指令
+    BranchBlocked
+}
+
+/// 保留站在一个周期内所处的状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RsState {
+    /// 等待 Qj 的结果
+    WaitingQj,
+    /// 等待 Qk 的结果
+    WaitingQk,
+    /// 操作数已就绪，等待流水线寄存器的最小延迟
+    Latched,
+    /// 操作数已就绪，等待空闲的执行单元
+    WaitingExecUnit,
+    /// 正在执行单元中执行
+    Executing,
+    /// 已经执行完毕，等待 CDB 广播结果
+    WaitingCdb
+}
+
+/// 停顿周期统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StallStats {
+    /// ROB 已满导致无法发射的周期数
+    pub rob_full: usize,
+    /// 各类保留站已满导致无法发射的周期数
+    pub rs_full: BTreeMap<String, usize>,
+    /// 各类功能单元都被占用导致无法发射的周期数
+    pub unit_busy: BTreeMap<String, usize>,
+    /// 操作数已经就绪但没有空闲执行单元（按保留站 * 周期计）
+    pub no_free_exec_unit: usize,
+    /// 等待操作数（按保留站 * 周期计）
+    pub operand_wait: usize,
+    /// 执行完毕但没有空闲 CDB（按结果 * 周期计）
+    pub cdb_wait: usize,
+    /// 没有空闲物理寄存器导致无法发射的周期数
+    pub free_list_empty: usize,
+    /// 计分板中因 WAR 冲突无法写结果（按功能单元 * 周期计）
+    pub war_hazard: usize,
+    /// 取指队列已满导致无法取指的周期数
+    pub fetch_queue_full: usize,
+    /// 等待跳转重定向导致无法取指的周期数
+    pub fetch_redirect: usize,
+    /// 操作数已经就绪但没有空闲 MSHR（按保留站 * 周期计）
+    pub mshr_full: usize,
+    /// store buffer 已满导致无法提交的周期数
+    pub store_buffer_full: usize
+}
+
+/// 发射槽统计（按槽 * 周期计）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SlotStats {
+    pub issued: usize,
+    pub rob_full: usize,
+    pub rs_full: BTreeMap<String, usize>,
+    pub unit_busy: BTreeMap<String, usize>,
+    pub free_list_empty: usize,
+    pub waw_hazard: usize,
+    pub data_hazard: usize,
+    pub queue_empty: usize,
+    pub fetch_starved: usize,
+    pub branch_blocked: usize
+}
+
+impl SlotStats {
+    /// 总发射槽数
+    pub fn total(&self) -> usize {
+        self.issued + self.rob_full + self.rs_full.values().sum::<usize>() + self.unit_busy.values().sum::<usize>() + self.free_list_empty + self.waw_hazard + self.data_hazard + self.queue_empty + self.fetch_starved + self.branch_blocked
+    }
+}
+
+/// 某类保留站各状态的统计（按保留站 * 周期计）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RsStateStats {
+    pub waiting_qj: usize,
+    pub waiting_qk: usize,
+    pub latched: usize,
+    pub waiting_exec_unit: usize,
+    pub executing: usize,
+    pub waiting_cdb: usize
+}
+
+/// 一级 cache 的命中统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub name: String,
+    pub accesses: usize,
+    pub hits: usize,
+    pub misses: usize,
+    /// 替换出脏块、写回下一级的次数
+    pub writebacks: usize,
+    pub hit_rate: f64
+}
+
+impl CacheStats {
+    pub fn new(name: &str, hits: usize, misses: usize, writebacks: usize) -> Self {
+        let accesses = hits + misses;
+        Self {
+            name: name.to_string(),
+            accesses,
+            hits,
+            misses,
+            writebacks,
+            hit_rate: if accesses > 0 { hits as f64 / accesses as f64 } else { 0.0 }
+        }
+    }
+}
+
+/// 非阻塞 cache 的 MSHR 统计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MshrStats {
+    /// MSHR 个数
+    pub count: usize,
+    /// 分配了 MSHR 的主缺失数
+    pub primary: usize,
+    /// 合并到已有 MSHR 的次级缺失数
+    pub merged: usize,
+    /// 因 MSHR 用尽而被拒绝的访问次数
+    pub full: usize,
+    /// 同时未完成的缺失数的峰值
+    pub peak: usize,
+    /// 访存并行度：至少有一个缺失未完成的周期内，平均未完成的缺失数
+    pub mlp: f64
+}
+
+/// 运行统计信息，由 `Cpu::run` 返回
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Stats {
+    /// 总周期数
+    pub cycles: usize,
+    /// 提交的指令数
+    pub committed: usize,
+    pub ipc: f64,
+    pub cpi: f64,
+    /// 每种指令提交的条数
+    pub opcode_counts: BTreeMap<String, usize>,
+    /// 每类执行单元的利用率
+    pub unit_utilization: BTreeMap<String, f64>,
+    /// 平均 ROB 占用项数
+    pub avg_rob_occupancy: f64,
+    /// 平均保留站占用个数
+    pub avg_rs_occupancy: f64,
+    /// CDB 广播的总次数
+    pub cdb_broadcasts: usize,
+    /// 平均每周期的 CDB 广播次数
+    pub avg_cdb_broadcasts: f64,
+    /// CDB 利用率，CDB 数量不限时为 None
+    pub cdb_utilization: Option<f64>,
+    /// 跳转导致取指重定向的次数
+    pub redirects: usize,
+    /// 各级 cache 的命中统计，没有 cache 时为空
+    pub cache: Vec<CacheStats>,
+    /// 平均访存延迟，没有 cache 时为 None
+    pub avg_memory_latency: Option<f64>,
+    /// MSHR 统计，没有设置 MSHR 时为 None
+    pub mshr: Option<MshrStats>,
+    /// 停顿统计
+    pub stalls: StallStats,
+    /// 发射槽分类统计
+    pub slots: SlotStats,
+    /// 各类保留站的状态统计
+    pub rs_states: BTreeMap<String, RsStateStats>,
+    /// 自顶向下的发射槽占比
+    pub breakdown: Vec<(String, f64)>,
+    /// 运行被强制停止的原因，正常结束时为 None
+    pub halted: Option<String>,
+    /// 程序通过停机寄存器结束时的退出码
+    pub exit_code: Option<i32>,
+    /// 每类执行单元的个数
+    #[serde(skip)]
+    unit_counts: BTreeMap<String, usize>,
+    /// 每类执行单元忙碌的单元周期数
+    #[serde(skip)]
+    unit_busy_cycles: BTreeMap<String, usize>,
+    #[serde(skip)]
+    rob_occupancy_sum: usize,
+    #[serde(skip)]
+    rs_occupancy_sum: usize,
+    /// 所有周期 CDB 数量之和，CDB 数量不限时为 None
+    #[serde(skip)]
+    cdb_capacity: Option<usize>,
+    /// 记录 CDB 的周期数
+    #[serde(skip)]
+    cdb_cycles: usize,
+    /// 已采样的周期数
+    #[serde(skip)]
+    samples: usize
+}
+
+/// 统计信息中用于计算派生指标的累加量，不出现在输出的统计信息中，由检查点单独保存
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct StatsAccumulators {
+    unit_counts: BTreeMap<String, usize>,
+    unit_busy_cycles: BTreeMap<String, usize>,
+    rob_occupancy_sum: usize,
+    rs_occupancy_sum: usize,
+    cdb_capacity: Option<usize>,
+    cdb_cycles: usize,
+    samples: usize
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 取出累加量，用于保存检查点
+    pub(crate) fn accumulators(&self) -> StatsAccumulators {
+        StatsAccumulators {
+            unit_counts: self.unit_counts.clone(),
+            unit_busy_cycles: self.unit_busy_cycles.clone(),
+            rob_occupancy_sum: self.rob_occupancy_sum,
+            rs_occupancy_sum: self.rs_occupancy_sum,
+            cdb_capacity: self.cdb_capacity,
+            cdb_cycles: self.cdb_cycles,
+            samples: self.samples
+        }
+    }
+
+    /// 从检查点恢复累加量
+    pub(crate) fn restore_accumulators(&mut self, acc: StatsAccumulators) {
+        self.unit_counts = acc.unit_counts;
+        self.unit_busy_cycles = acc.unit_busy_cycles;
+        self.rob_occupancy_sum = acc.rob_occupancy_sum;
+        self.rs_occupancy_sum = acc.rs_occupancy_sum;
+        self.cdb_capacity = acc.cdb_capacity;
+        self.cdb_cycles = acc.cdb_cycles;
+        self.samples = acc.samples;
+    }
+
+    /// 记录一条提交的指令
+    pub(crate) fn record_commit(&mut self, inst: &Instruction) {
+        self.committed += 1;
+        *self.opcode_counts.entry(inst.name().to_string()).or_insert(0) += 1;
+    }
+
+    /// 登记某类执行单元的个数
+    pub(crate) fn add_units(&mut self, name: &str, count: usize) {
+        *self.unit_counts.entry(name.to_string()).or_insert(0) += count;
+        self.unit_busy_cycles.entry(name.to_string()).or_insert(0);
+    }
+
+    /// 记录某类执行单元在本周期忙碌的个数
+    pub(crate) fn record_unit_busy(&mut self, name: &str, busy: usize) {
+        *self.unit_busy_cycles.entry(name.to_string()).or_insert(0) += busy;
+    }
+
+    /// 每周期采样 ROB 与保留站的占用
+    pub(crate) fn sample_occupancy(&mut self, rob_busy: usize, rs_busy: usize) {
+        self.rob_occupancy_sum += rob_busy;
+        self.rs_occupancy_sum += rs_busy;
+        self.samples += 1;
+    }
+
+    /// 记录 count 个发射槽的结果，停顿同时按周期计数一次
+    pub(crate) fn record_issue(&mut self, slot: IssueSlot, count: usize) {
+        match slot {
+            IssueSlot::Issued => self.slots.issued += count,
+            IssueSlot::RobFull => {
+                self.slots.rob_full += count;
+                self.stalls.rob_full += 1;
+            },
+            IssueSlot::RsFull(rs_type) => {
+                *self.slots.rs_full.entry(rs_type.name().to_string()).or_insert(0) += count;
+                *self.stalls.rs_full.entry(rs_type.name().to_string()).or_insert(0) += 1;
+            },
+            IssueSlot::UnitBusy(unit_type) => {
+                *self.slots.unit_busy.entry(unit_type.name().to_string()).or_insert(0) += count;
+                *self.stalls.unit_busy.entry(unit_type.name().to_string()).or_insert(0) += 1;
+            },
+            IssueSlot::FreeListEmpty => {
+                self.slots.free_list_empty += count;
+                self.stalls.free_list_empty += 1;
+            },
+            IssueSlot::WawHazard => self.slots.waw_hazard += count,
+            IssueSlot::DataHazard => self.slots.data_hazard += count,
+            IssueSlot::QueueEmpty => self.slots.queue_empty += count,
+            IssueSlot::FetchStarved => self.slots.fetch_starved += count,
+            IssueSlot::BranchBlocked => self.slots.branch_blocked += count
+        }
+    }
+
+    /// 记录本周期无法取指，full 为 true 表示取指队列已满，否则为等待重定向
+    pub(crate) fn record_fetch_stall(&mut self, full: bool) {
+        if full {
+            self.stalls.fetch_queue_full += 1;
+        }else {
+            self.stalls.fetch_redirect += 1;
+        }
+    }
+
+    /// 记录一次取指重定向
+    pub(crate) fn record_redirect(&mut self) {
+        self.redirects += 1;
+    }
+
+    /// 记录一个保留站在本周期的状态
+    pub(crate) fn record_rs_state(&mut self, rs_type: ResStationType, state: RsState) {
+        let entry = self.rs_states.entry(rs_type.name().to_string()).or_default();
+        match state {
+            RsState::WaitingQj => {
+                entry.waiting_qj += 1;
+                self.stalls.operand_wait += 1;
+            },
+            RsState::WaitingQk => {
+                entry.waiting_qk += 1;
+                self.stalls.operand_wait += 1;
+            },
+            RsState::Latched => entry.latched += 1,
+            RsState::WaitingExecUnit => entry.waiting_exec_unit += 1,
+            RsState::Executing => entry.executing += 1,
+            RsState::WaitingCdb => entry.waiting_cdb += 1
+        }
+    }
+
+    /// 记录本周期 CDB 的使用情况：广播的结果数、等待 CDB 的结果数以及 CDB 数量
+    pub(crate) fn record_cdb(&mut self, used: usize, waiting: usize, count: Option<usize>) {
+        self.cdb_broadcasts += used;
+        self.stalls.cdb_wait += waiting;
+        self.cdb_cycles += 1;
+        self.cdb_capacity = count.map(|count| self.cdb_capacity.unwrap_or(0) + count);
+    }
+
+    /// 自顶向下的瓶颈分析：各类发射槽占总槽数的比例，按从大到小排序；
+    /// 指令队列为空只发生在所有指令都已发射之后的排空阶段，不是瓶颈，因此不计入
+    pub fn bottleneck(&self) -> Vec<(String, f64)> {
+        let total = self.slots.total() - self.slots.queue_empty;
+        if total == 0 {
+            return vec![]
+        }
+        let mut breakdown = vec![
+            ("issued".to_string(), self.slots.issued),
+            ("frontend: fetch/decode".to_string(), self.slots.fetch_starved),
+            ("frontend: branch".to_string(), self.slots.branch_blocked),
+            ("backend: ROB".to_string(), self.slots.rob_full),
+            ("backend: free list".to_string(), self.slots.free_list_empty),
+            ("backend: WAW".to_string(), self.slots.waw_hazard),
+            ("backend: RAW".to_string(), self.slots.data_hazard),
+        ];
+        for (name, count) in self.slots.rs_full.iter() {
+            breakdown.push((format!("backend: RS {}", name), *count));
+        }
+        for (name, count) in self.slots.unit_busy.iter() {
+            breakdown.push((format!("backend: unit {}", name), *count));
+        }
+        let mut breakdown: Vec<(String, f64)> = breakdown.into_iter()
+            .map(|(name, count)| (name, count as f64 / total as f64))
+            .collect();
+        breakdown.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        breakdown
+    }
+
+    /// 除成功发射以外占比最大的瓶颈，即最值得增加的资源
+    pub fn top_bottleneck(&self) -> Option<(String, f64)> {
+        self.bottleneck().into_iter().find(|(name, _)| name != "issued")
+    }
+
+    /// 运行结束时计算派生指标
+    pub(crate) fn finish(&mut self, cycles: usize) {
+        self.cycles = cycles;
+        self.ipc = if cycles > 0 { self.committed as f64 / cycles as f64 } else { 0.0 };
+        self.cpi = if self.committed > 0 { cycles as f64 / self.committed as f64 } else { 0.0 };
+        for (name, count) in self.unit_counts.iter() {
+            let busy = self.unit_busy_cycles[name];
+            let total = count * self.samples;
+            let util = if total > 0 { busy as f64 / total as f64 } else { 0.0 };
+            self.unit_utilization.insert(name.clone(), util);
+        }
+        if self.samples > 0 {
+            self.avg_rob_occupancy = self.rob_occupancy_sum as f64 / self.samples as f64;
+            self.avg_rs_occupancy = self.rs_occupancy_sum as f64 / self.samples as f64;
+        }
+        if self.cdb_cycles > 0 {
+            self.avg_cdb_broadcasts = self.cdb_broadcasts as f64 / self.cdb_cycles as f64;
+        }
+        self.cdb_utilization = self.cdb_capacity
+            .filter(|capacity| *capacity > 0)
+            .map(|capacity| self.cdb_broadcasts as f64 / capacity as f64);
+        self.breakdown = self.bottleneck();
+    }
+
+    /// 序列化为 JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<28}{:>12}", "cycles", self.cycles)?;
+        writeln!(f, "{:<28}{:>12}", "committed", self.committed)?;
+        if let Some(halted) = &self.halted {
+            writeln!(f, "halted: {}", halted)?;
+        }
+        if let Some(code) = self.exit_code {
+            writeln!(f, "{:<28}{:>12}", "exit code", code)?;
+        }
+        writeln!(f, "{:<28}{:>12.3}", "IPC", self.ipc)?;
+        writeln!(f, "{:<28}{:>12.3}", "CPI", self.cpi)?;
+        writeln!(f, "{:<28}{:>12.3}", "avg ROB occupancy", self.avg_rob_occupancy)?;
+        writeln!(f, "{:<28}{:>12.3}", "avg RS occupancy", self.avg_rs_occupancy)?;
+        writeln!(f, "{:<28}{:>12.3}", "avg CDB broadcasts", self.avg_cdb_broadcasts)?;
+        if let Some(util) = self.cdb_utilization {
+            writeln!(f, "{:<28}{:>11.1}%", "CDB utilization", util * 100.0)?;
+        }
+        writeln!(f, "-- opcode --")?;
+        for (name, count) in self.opcode_counts.iter() {
+            writeln!(f, "{:<28}{:>12}", name, count)?;
+        }
+        if !self.unit_utilization.is_empty() {
+            writeln!(f, "-- unit utilization --")?;
+            for (name, util) in self.unit_utilization.iter() {
+                writeln!(f, "{:<28}{:>11.1}%", name, util * 100.0)?;
+            }
+        }
+        writeln!(f, "-- stall --")?;
+        writeln!(f, "{:<28}{:>12}", "ROB full", self.stalls.rob_full)?;
+        for (name, count) in self.stalls.rs_full.iter() {
+            writeln!(f, "{:<28}{:>12}", format!("RS full ({})", name), count)?;
+        }
+        for (name, count) in self.stalls.unit_busy.iter() {
+            writeln!(f, "{:<28}{:>12}", format!("unit busy ({})", name), count)?;
+        }
+        writeln!(f, "{:<28}{:>12}", "no free exec unit", self.stalls.no_free_exec_unit)?;
+        writeln!(f, "{:<28}{:>12}", "operand wait", self.stalls.operand_wait)?;
+        writeln!(f, "{:<28}{:>12}", "CDB wait", self.stalls.cdb_wait)?;
+        writeln!(f, "{:<28}{:>12}", "free list empty", self.stalls.free_list_empty)?;
+        writeln!(f, "{:<28}{:>12}", "WAR hazard", self.stalls.war_hazard)?;
+        writeln!(f, "{:<28}{:>12}", "fetch queue full", self.stalls.fetch_queue_full)?;
+        writeln!(f, "{:<28}{:>12}", "fetch redirect", self.stalls.fetch_redirect)?;
+        writeln!(f, "{:<28}{:>12}", "MSHR full", self.stalls.mshr_full)?;
+        writeln!(f, "{:<28}{:>12}", "store buffer full", self.stalls.store_buffer_full)?;
+        writeln!(f, "{:<28}{:>12}", "redirects", self.redirects)?;
+        if !self.cache.is_empty() {
+            writeln!(f, "-- cache (accesses / hits / misses / writebacks / hit rate) --")?;
+            for cache in self.cache.iter() {
+                writeln!(
+                    f, "{:<16}{:>8}{:>8}{:>8}{:>8}{:>11.1}%",
+                    cache.name, cache.accesses, cache.hits, cache.misses, cache.writebacks, cache.hit_rate * 100.0
+                )?;
+            }
+        }
+        if let Some(latency) = self.avg_memory_latency {
+            writeln!(f, "{:<28}{:>12.3}", "avg memory latency", latency)?;
+        }
+        if let Some(mshr) = &self.mshr {
+            writeln!(f, "-- MSHR --")?;
+            writeln!(f, "{:<28}{:>12}", "MSHRs", mshr.count)?;
+            writeln!(f, "{:<28}{:>12}", "primary misses", mshr.primary)?;
+            writeln!(f, "{:<28}{:>12}", "merged misses", mshr.merged)?;
+            writeln!(f, "{:<28}{:>12}", "MSHR full", mshr.full)?;
+            writeln!(f, "{:<28}{:>12}", "peak outstanding", mshr.peak)?;
+            writeln!(f, "{:<28}{:>12.3}", "MLP", mshr.mlp)?;
+        }
+        if !self.rs_states.is_empty() {
+            writeln!(f, "-- reservation station (wait Qj / wait Qk / latch / wait unit / exec / wait CDB) --")?;
+            for (name, state) in self.rs_states.iter() {
+                writeln!(
+                    f, "{:<16}{:>8}{:>8}{:>8}{:>8}{:>8}{:>8}", 
+                    name, state.waiting_qj, state.waiting_qk, state.latched, 
+                    state.waiting_exec_unit, state.executing, state.waiting_cdb
+                )?;
+            }
+        }
+        writeln!(f, "-- issue slots --")?;
+        for (name, frac) in self.breakdown.iter() {
+            writeln!(f, "{:<28}{:>11.1}%", name, frac * 100.0)?;
+        }
+        match self.top_bottleneck() {
+            Some((name, _)) => write!(f, "{:<28}{:>12}", "bottleneck", name),
+            None => write!(f, "{:<28}{:>12}", "bottleneck", "-")
+        }
+    }
+}
+
+/// 采样模拟的统计：各采样区间的 IPC 以及对整体 IPC 的估计
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SamplingStats {
+    /// 功能模拟（快进）的指令数
+    pub fast_forwarded: usize,
+    /// 详细模拟的指令数，包括预热
+    pub detailed: usize,
+    /// 每个采样区间测量的 (指令数, 周期数)
+    pub samples: Vec<(usize, usize)>,
+    /// 所有采样区间的指令数之和除以周期数之和
+    pub ipc: f64,
+    /// 各采样区间 IPC 的平均值
+    pub mean_ipc: f64,
+    /// 各采样区间 IPC 的样本标准差
+    pub std_dev: f64,
+    /// 平均 IPC 的 95% 置信区间，少于两个采样区间时为 None
+    pub confidence_interval: Option<(f64, f64)>
+}
+
+impl SamplingStats {
+    /// 正态分布 97.5% 分位数，用于 95% 置信区间
+    const Z_95: f64 = 1.96;
+
+    pub(crate) fn new(fast_forwarded: usize, detailed: usize, samples: Vec<(usize, usize)>) -> Self {
+        let ipcs: Vec<f64> = samples.iter()
+            .filter(|(_, cycles)| *cycles > 0)
+            .map(|(insts, cycles)| *insts as f64 / *cycles as f64)
+            .collect();
+        let n = ipcs.len() as f64;
+        let (insts, cycles) = samples.iter().fold((0, 0), |(insts, cycles), sample| (insts + sample.0, cycles + sample.1));
+        let mean_ipc = if ipcs.is_empty() { 0.0 } else { ipcs.iter().sum::<f64>() / n };
+        let std_dev = if ipcs.len() < 2 {
+            0.0
+        }else {
+            (ipcs.iter().map(|ipc| (ipc - mean_ipc).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        };
+        let half_width = Self::Z_95 * std_dev / n.sqrt();
+        Self {
+            fast_forwarded,
+            detailed,
+            samples,
+            ipc: if cycles > 0 { insts as f64 / cycles as f64 } else { 0.0 },
+            mean_ipc,
+            std_dev,
+            confidence_interval: (ipcs.len() >= 2).then_some((mean_ipc - half_width, mean_ipc + half_width))
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+impl fmt::Display for SamplingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<28}{:>12}", "fast-forwarded", self.fast_forwarded)?;
+        writeln!(f, "{:<28}{:>12}", "detailed", self.detailed)?;
+        writeln!(f, "{:<28}{:>12}", "samples", self.samples.len())?;
+        writeln!(f, "{:<28}{:>12.3}", "IPC", self.ipc)?;
+        writeln!(f, "{:<28}{:>12.3}", "mean sample IPC", self.mean_ipc)?;
+        writeln!(f, "{:<28}{:>12.3}", "std dev", self.std_dev)?;
+        match self.confidence_interval {
+            Some((low, high)) => write!(f, "{:<28}{:>12}", "95% CI", format!("{:.3}-{:.3}", low, high)),
+            None => write!(f, "{:<28}{:>12}", "95% CI", "-")
+        }
+    }
+}
@@ -1,9 +1,11 @@
-use std::{collections::VecDeque, io::Write};
+use std::{collections::VecDeque, io::Write, fmt, str::FromStr};
 use crate::trace::Trace;
 
-use super::{ Instruction, Cpu, Memory };
+use super::{ Instruction, Cpu, ArchState, Memory, Width, Endian, Misaligned, MemFault, MemoryMap, Device, Stats, IssueSlot, RsState, Retired, PhysRegFile, CacheHierarchy, stats::StatsAccumulators, format_regs, opt };
 
 use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use serde::{ Serialize, Deserialize };
 
 pub const ADD_CYCLES: usize = 2;
 pub const SUB_CYCLES: usize = 2;
@@ -12,8 +14,193 @@ pub const DIV_CYCLES: usize = 24;
 pub const LOAD_CYCLES: usize = 2;
 pub const JUMP_CYCLES: usize = 1;
 
+/// 每周期最多发射的指令数
+pub const ISSUE_WIDTH: usize = 8;
 
-#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+/// 默认的 store buffer 项数
+pub const STORE_BUFFER_SIZE: usize = 8;
+
+/// 指令在执行单元中所需的周期数
+pub fn exec_cycles(inst: &Instruction) -> usize {
+    match inst {
+        Instruction::Add(_) => ADD_CYCLES,
+        Instruction::Sub(_) => SUB_CYCLES,
+        Instruction::Mul(_) => MUL_CYCLES,
+        Instruction::Div(_) => DIV_CYCLES,
+        Instruction::Ld(..) | Instruction::Sd(..) => LOAD_CYCLES,
+        Instruction::Jump(_, _) => JUMP_CYCLES,
+        Instruction::Invalid => 0
+    }
+}
+
+/// 从就绪的保留站中选择指令进入执行单元的策略
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SelectPolicy {
+    /// 最老的指令优先
+    OldestFirst,
+    /// 保留站编号小的优先
+    Position,
+    /// 随机选择，参数为随机数种子
+    Random(u64),
+    /// 关键路径优先：依赖该指令结果的后续指令链越长越优先，相同时老的优先
+    CriticalPath
+}
+
+impl FromStr for SelectPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest" => Ok(SelectPolicy::OldestFirst),
+            "position" => Ok(SelectPolicy::Position),
+            "critical" => Ok(SelectPolicy::CriticalPath),
+            "random" => Ok(SelectPolicy::Random(0)),
+            _ => match s.strip_prefix("random:") {
+                Some(seed) => seed.parse()
+                    .map(SelectPolicy::Random)
+                    .map_err(|_| format!("invalid seed: {}", seed)),
+                None => Err(format!("unknown select policy: {}", s))
+            }
+        }
+    }
+}
+
+/// 执行完毕的结果多于 CDB 数量时的仲裁策略
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CdbArbitration {
+    /// 最老的指令优先
+    OldestFirst,
+    /// 执行单元编号小的优先
+    UnitOrder,
+    /// 执行单元轮流优先
+    RoundRobin
+}
+
+impl FromStr for CdbArbitration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "oldest" => Ok(CdbArbitration::OldestFirst),
+            "unit" => Ok(CdbArbitration::UnitOrder),
+            "round-robin" => Ok(CdbArbitration::RoundRobin),
+            _ => Err(format!("unknown CDB arbitration: {}", s))
+        }
+    }
+}
+
+/// 流水线各阶段之间的最小延迟（周期数），
+/// 0 表示前一阶段完成的同一周期内即可进入下一阶段
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct StageLatency {
+    /// 发射到派遣到执行单元
+    pub issue_to_dispatch: usize,
+    /// 操作数被 CDB 唤醒到可以被选择执行
+    pub wakeup_to_select: usize,
+    /// 写回结果到提交
+    pub writeback_to_commit: usize
+}
+
+impl StageLatency {
+    /// 教材中的时序：发射的下一周期开始执行，
+    /// 写回的下一周期操作数可用，写回的下一周期才能提交
+    pub fn textbook() -> Self {
+        Self{ issue_to_dispatch: 1, wakeup_to_select: 1, writeback_to_commit: 1 }
+    }
+}
+
+impl FromStr for StageLatency {
+    type Err = String;
+
+    /// 解析 `issue_to_dispatch,wakeup_to_select,writeback_to_commit` 格式，例如 `1,1,1`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let latency: Vec<usize> = s.split(',')
+            .map(|item| item.trim().parse().map_err(|_| format!("invalid latency: {}", s)))
+            .collect::<Result<_, _>>()?;
+        match latency.as_slice() {
+            [issue_to_dispatch, wakeup_to_select, writeback_to_commit] => Ok(Self{
+                issue_to_dispatch: *issue_to_dispatch,
+                wakeup_to_select: *wakeup_to_select,
+                writeback_to_commit: *writeback_to_commit
+            }),
+            _ => Err(format!("expect three latencies: {}", s))
+        }
+    }
+}
+
+/// 取指与译码前端，未设置时为理想前端：
+/// 每周期可以直接从指令队列中取出任意条指令发射
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrontEnd {
+    /// 每周期最多取指的条数
+    pub fetch_width: usize,
+    /// 取指队列的深度
+    pub queue_depth: usize,
+    /// 取指后经过多少周期完成译码，0 表示取指的同一周期即可发射
+    pub decode_delay: usize,
+    /// 跳转指令被预测为跳转，取到跳转指令后重定向取指所需的周期数，
+    /// 本周期跳转之后的指令不再取指
+    pub redirect_penalty: usize
+}
+
+impl FromStr for FrontEnd {
+    type Err = String;
+
+    /// 解析 `fetch_width,queue_depth,decode_delay,redirect_penalty` 格式，例如 `4,16,1,2`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: Vec<usize> = s.split(',')
+            .map(|item| item.trim().parse().map_err(|_| format!("invalid front end: {}", s)))
+            .collect::<Result<_, _>>()?;
+        match config.as_slice() {
+            [0, _, _, _] | [_, 0, _, _] => Err(format!("fetch width and queue depth must be positive: {}", s)),
+            [fetch_width, queue_depth, decode_delay, redirect_penalty] => Ok(Self{
+                fetch_width: *fetch_width,
+                queue_depth: *queue_depth,
+                decode_delay: *decode_delay,
+                redirect_penalty: *redirect_penalty
+            }),
+            _ => Err(format!("expect four numbers: {}", s))
+        }
+    }
+}
+
+/// 寄存器重命名方式
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RenameMode {
+    /// 以 ROB 标签作为重命名，结果保存在 ROB 中，提交时写入寄存器堆
+    Rob,
+    /// 显式的物理寄存器堆（R10K 风格），参数为物理寄存器个数，
+    /// 结果直接写入物理寄存器，ROB 只保存元数据
+    PhysRegFile(usize),
+    /// 原始的 Tomasulo 算法（IBM 360/91），以保留站作为重命名标签，没有 ROB，
+    /// 结果由 CDB 直接写入寄存器，指令写回即完成，不按程序顺序提交，因此没有精确异常
+    Classic
+}
+
+impl FromStr for RenameMode {
+    type Err = String;
+
+    /// 解析 `rob`、`classic` 或者 `prf:物理寄存器个数`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rob" => Ok(RenameMode::Rob),
+            "classic" => Ok(RenameMode::Classic),
+            _ => match s.strip_prefix("prf:") {
+                Some(count) => match count.parse() {
+                    Ok(count) if count > 32 => Ok(RenameMode::PhysRegFile(count)),
+                    _ => Err(format!("invalid physical register count: {}", count))
+                },
+                None => Err(format!("unknown rename mode: {}", s))
+            }
+        }
+    }
+}
+
+/// 默认连续多少个周期没有 CDB 广播、提交或者 store buffer 写入内存即认为发生了死锁
+pub const DEFAULT_WATCHDOG_CYCLES: usize = 1000;
+
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Serialize, Deserialize)]
 pub enum ResStationType {
     AddSub,
     MulDiv,
@@ -21,12 +208,24 @@ pub enum ResStationType {
     JUMP
 }
 
+impl ResStationType {
+    /// 保留站类型的名称，用于统计与打印
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResStationType::AddSub => "AddSub",
+            ResStationType::MulDiv => "MulDiv",
+            ResStationType::LoadStore => "LoadStore",
+            ResStationType::JUMP => "Jump"
+        }
+    }
+}
+
 impl From<Instruction> for ResStationType {
     fn from(item: Instruction) -> ResStationType {
         match item {
             Instruction::Add(_) | Instruction::Sub(_) => { ResStationType::AddSub },
             Instruction::Mul(_) | Instruction::Div(_) => { ResStationType::MulDiv },
-            Instruction::Ld(_, _, _) | Instruction::Sd(_, _, _) => { ResStationType::LoadStore },
+            Instruction::Ld(..) | Instruction::Sd(..) => { ResStationType::LoadStore },
             Instruction::Jump(_, _) => { ResStationType::JUMP },
             _ => { panic!("[Error] Invalid instruction") }
         }
@@ -34,7 +233,7 @@ impl From<Instruction> for ResStationType {
 }
 
 /// 保留站
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReservedStation {
     /// 保留站类型
     rs_type: ResStationType,
@@ -47,7 +246,7 @@ pub struct ReservedStation {
     
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ResStationInner {
     /// 内存地址，仅 load, store 使用
     address: Option<u32>,
@@ -61,12 +260,18 @@ pub struct ResStationInner {
     rs_value: Option<i32>,
     /// Vk
     rt_value: Option<i32>,
-    /// 记录 ROB 的地址
-    dest: Option<usize>
+    /// 记录 ROB 的地址，经典 Tomasulo 模式下为保留站自身的编号
+    dest: Option<usize>,
+    /// 指令在程序中的序号
+    pc: usize,
+    /// 发射时的周期数
+    issued_at: usize,
+    /// 最后一个操作数被 CDB 唤醒时的周期数
+    woken_at: usize
 }
 
 /// ROB
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReorderBuffer {
     busy: bool,
     ready: bool,
@@ -75,34 +280,53 @@ pub struct ReorderBuffer {
 }
 
 impl ReorderBuffer {
+    /// ROB 的标签，保留站以及寄存器状态通过标签引用 ROB
+    pub fn tag(&self) -> usize {
+        self.index
+    }
+
     pub(crate) fn init() -> Self {
         let mut rng = rand::thread_rng();
-        let rob = Self{
+        Self{
             busy: false,
             ready: false,
             index: rng.gen::<usize>(),
             inner: ROBInner {
                 inst: None,
+                pc: None,
                 dest: None,
-                value: None
+                value: None,
+                address: None,
+                written_at: 0,
+                phys: None,
+                fault: None
             }
-        };
-        rob
+        }
     }
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ROBInner {   
     inst: Option<Instruction>,
+    /// 指令在程序中的序号
+    pc: Option<usize>,
     /// 存储将写到寄存器的编号
     dest: Option<usize>,
     /// 存储计算的结果
-    value: Option<i32>
+    value: Option<i32>,
+    /// 访存指令的地址
+    address: Option<u32>,
+    /// 结果写回时的周期数
+    written_at: usize,
+    /// 物理寄存器堆模式下目标寄存器的 (新物理寄存器, 之前的映射)
+    phys: Option<(usize, usize)>,
+    /// 执行时发生的访存异常，在提交时处理
+    fault: Option<MemFault>
 }
 
 /// 寄存器状态
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct RegisterStatus {
     busy: bool,
     /// reorder 不应该标识为数组的索引
@@ -111,7 +335,32 @@ pub struct RegisterStatus {
     reorder: Option<usize>
 }
 
-#[derive(Debug)]
+impl RegisterStatus {
+    /// 寄存器正在等待的 ROB 标签
+    pub fn reorder(&self) -> Option<usize> {
+        if self.busy { self.reorder } else { None }
+    }
+}
+
+/// store buffer 中已经提交、尚未写入内存的 store
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StoreEntry {
+    /// 指令在程序中的序号
+    pub pc: usize,
+    pub address: u32,
+    pub width: Width,
+    pub value: i32,
+    /// 写入完成的周期数，尚未开始写入时为 None
+    pub done_at: Option<usize>
+}
+
+impl fmt::Display for StoreEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<6}{:#010x}{:>6}{:>12}{:>10}", self.pc, self.address, self.width.bytes(), self.value, opt(&self.done_at))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecUint {
     busy: bool,
     rs_type: ResStationType,
@@ -120,7 +369,138 @@ pub struct ExecUint {
     rs_index: usize
 }
 
+impl fmt::Display for ReservedStation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{:<10}{:<6}{:<6}{:<16}{:>8}{:>8}{:>22}{:>22}{:>22}{:>8}",
+            self.rs_type.name(), self.busy, self.exec, opt(&self.inner.inst),
+            opt(&self.inner.rs_value), opt(&self.inner.rt_value),
+            opt(&self.inner.rs_index), opt(&self.inner.rt_index),
+            opt(&self.inner.dest), opt(&self.inner.address)
+        )
+    }
+}
+
+impl fmt::Display for ReorderBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f, "{:>22}  {:<6}{:<6}{:>6}  {:<16}{:>6}{:>12}{:>8}",
+            self.index, self.busy, self.ready, opt(&self.inner.pc), opt(&self.inner.inst),
+            opt(&self.inner.dest.map(|dest| match self.inner.phys {
+                Some((phys, _)) => format!("R{}/P{}", dest, phys),
+                None => format!("R{}", dest)
+            })),
+            opt(&self.inner.value), opt(&self.inner.address)
+        )
+    }
+}
+
+impl fmt::Display for RegisterStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.busy { write!(f, "{}", opt(&self.reorder)) }
+        else { write!(f, "-") }
+    }
+}
+
+impl fmt::Display for ExecUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.busy {
+            write!(f, "{:<10} busy rs={} left={}", self.rs_type.name(), self.rs_index, self.cycles)
+        }else {
+            write!(f, "{:<10} idle", self.rs_type.name())
+        }
+    }
+}
+
+/// 一个周期内发生的事件，由 `TomasuloCpu::step` 返回
+#[derive(Debug, Clone, Default)]
+pub struct CycleEvents {
+    /// 该周期的周期数
+    pub cycle: usize,
+    /// 发射的指令，(序号, 指令)
+    pub issued: Vec<(usize, Instruction)>,
+    /// 取指的指令，(序号, 指令)，理想前端时为空
+    pub fetched: Vec<(usize, Instruction)>,
+    /// 本周期各发射槽的结果
+    pub issue_slots: Vec<IssueSlot>,
+    /// 开始在执行单元中执行的指令，(序号, 指令)
+    pub dispatched: Vec<(usize, Instruction)>,
+    /// CDB 上的广播
+    pub written_back: Vec<CdbBroadcast>,
+    /// 提交的指令
+    pub committed: Vec<Retired>,
+    /// 运行是否已经结束
+    pub done: bool
+}
+
+/// 一次 CDB 广播
+#[derive(Debug, Clone, Copy)]
+pub struct CdbBroadcast {
+    /// 广播的 ROB 标签
+    pub tag: usize,
+    /// 指令在程序中的序号
+    pub pc: usize,
+    pub inst: Instruction,
+    pub value: i32
+}
+
+/// 运行被强制停止的原因
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum HaltReason {
+    /// 达到周期上限
+    CycleLimit(usize),
+    /// 连续若干个周期既没有 CDB 广播和提交，store buffer 也没有缩短
+    Deadlock(usize),
+    /// 指令发生访存异常，(指令序号, 异常)
+    Fault(usize, MemFault),
+    /// 遇到非法指令，指令序号
+    InvalidInstruction(usize)
+}
+
+impl fmt::Display for HaltReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HaltReason::CycleLimit(limit) => write!(f, "cycle limit {} reached", limit),
+            HaltReason::Deadlock(cycles) => write!(f, "no progress for {} cycles", cycles),
+            HaltReason::Fault(pc, fault) => write!(f, "pc {}: {}", pc, fault),
+            HaltReason::InvalidInstruction(pc) => write!(f, "pc {}: invalid instruction", pc)
+        }
+    }
+}
+
+/// 运行被强制停止时的诊断信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Halt {
+    pub reason: HaltReason,
+    /// 停止时的周期数
+    pub cycle: usize,
+    /// 最老的未提交指令及其等待的原因
+    pub oldest: String,
+    /// ROB 与保留站的状态
+    pub dump: String
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "[Error] cpu halted at cycle {}: {}", self.cycle, self.reason)?;
+        writeln!(f, "oldest unretired: {}", self.oldest)?;
+        write!(f, "{}", self.dump)
+    }
+}
+
 pub struct TomasuloCpu<'a> {
+    /// 追踪文件
+    trace: &'a mut Trace,
+    /// 本周期发生的事件
+    events: CycleEvents,
+    /// 保存在检查点中的模拟器状态
+    state: TomasuloState
+}
+
+/// 除追踪文件与本周期的事件之外的全部模拟器状态，保存在检查点中；
+/// 挂载的设备不参与序列化
+#[derive(Serialize, Deserialize)]
+struct TomasuloState {
     /// 是否完成
     done: bool,
     /// 周期数
@@ -131,6 +511,16 @@ pub struct TomasuloCpu<'a> {
     regs: [i32;32],
     /// 指令队列
     instruction_queue: VecDeque<Instruction>,
+    /// 取指前端，None 表示理想前端
+    front_end: Option<FrontEnd>,
+    /// 取指队列，(指令, 译码完成的周期数)
+    fetch_queue: VecDeque<(Instruction, usize)>,
+    /// 重定向结束、可以继续取指的周期数
+    fetch_resume_at: usize,
+    /// 寄存器重命名方式
+    rename_mode: RenameMode,
+    /// 物理寄存器堆，仅在物理寄存器堆模式下存在
+    prf: Option<PhysRegFile>,
     /// 保留站
     rs: Vec<ReservedStation>,
     /// ROB
@@ -139,22 +529,57 @@ pub struct TomasuloCpu<'a> {
     exec_units: Vec<ExecUint>,
     /// 内存
     memory: Memory,
-    /// 追踪文件
-    trace: &'a mut Trace
+    /// 数据 cache，None 表示 load 固定需要 `LOAD_CYCLES` 个周期
+    cache: Option<CacheHierarchy>,
+    /// 已提交的 store，按程序顺序依次写入内存
+    store_buffer: VecDeque<StoreEntry>,
+    store_buffer_size: usize,
+    /// 下一条发射的指令序号
+    pc: usize,
+    /// 流水线各阶段之间的最小延迟
+    stage_latency: StageLatency,
+    /// 选择就绪指令的策略
+    select_policy: SelectPolicy,
+    /// 随机选择策略使用的随机数生成器
+    select_rng: ChaCha12Rng,
+    /// CDB 的数量，None 表示不限制
+    cdb_count: Option<usize>,
+    /// CDB 仲裁策略
+    cdb_arbitration: CdbArbitration,
+    /// 轮流仲裁时当前优先的执行单元
+    cdb_round_robin: usize,
+    /// 周期上限
+    cycle_limit: Option<usize>,
+    /// 连续多少个周期没有进展即停止运行
+    watchdog: usize,
+    /// 连续没有进展的周期数
+    idle_cycles: usize,
+    /// 被强制停止时的诊断信息
+    halt: Option<Halt>,
+    /// 统计信息
+    stats: Stats
 }
 
+/// 检查点：模拟器状态，以及统计信息中不参与序列化的累加量
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<S> {
+    state: S,
+    stats_accumulators: StatsAccumulators
+}
 
 impl<'a> Cpu for TomasuloCpu<'a> {
     fn add_inst(&mut self, inst: Instruction) {
-        self.instruction_queue.push_back(inst);
+        self.state.instruction_queue.push_back(inst);
     }
 
-    fn run(&mut self) {
-        loop {
-            if !self.done(){ self.mult_issue(8); }
-            else { break; }
+    fn run(&mut self) -> Stats {
+        while !self.done() {
+            self.step();
+        }
+        if let Some(halt) = &self.state.halt {
+            eprintln!("{}", halt);
         }
-        println!("[Debug] Cpu run finished, cycles: {}", self.cycles);
+        self.stats()
     }
 
     fn trace<S>(&mut self, s: S) 
@@ -165,24 +590,28 @@ impl<'a> Cpu for TomasuloCpu<'a> {
     }
 
     fn write_memory(&mut self, addr: u32, val: i32) {
-        self.memory.write(addr, val);
+        self.state.memory.write(addr, val);
+    }
+
+    fn set_regs(&mut self, index: usize, number: i32) {
+        self.state.regs[index] = number;
+        if let Some(prf) = &mut self.state.prf {
+            prf.set(index, number);
+        }
+    }
+
+    fn state(&self) -> ArchState {
+        ArchState::new(self.state.regs, self.state.memory.words())
+    }
+
+    fn memory_map(&self) -> Option<&MemoryMap> {
+        self.state.memory.map()
     }
 }
 
 impl<'a> TomasuloCpu<'a> {
     pub fn new(trace: &'a mut Trace) -> Self {
-        let mut cpu = Self {
-            done: false,
-            cycles: 0,
-            reg_stat: vec![RegisterStatus{ busy: false, reorder: None }; 32],
-            regs: [0i32;32],
-            instruction_queue: VecDeque::new(),
-            rs: vec![],
-            rob: vec![],
-            exec_units: vec![],
-            memory: Memory::init(),
-            trace: trace
-        };
+        let mut cpu = Self::new_empty(trace);
         // 为 CPU 添加保留站
         cpu.add_rs(ResStationType::AddSub, 3);
         cpu.add_rs(ResStationType::MulDiv, 2);
@@ -198,21 +627,399 @@ impl<'a> TomasuloCpu<'a> {
         cpu
     }
 
-    pub fn set_regs(&mut self, index: usize, number: i32) {
-        self.regs[index] = number;
+    /// 创建一个没有保留站、ROB 与执行单元的 CPU，
+    /// 需要通过 `add_rs`、`add_rob`、`add_exec_unit` 自行配置
+    pub fn new_empty(trace: &'a mut Trace) -> Self {
+        Self {
+            trace,
+            events: CycleEvents::default(),
+            state: TomasuloState {
+                done: false,
+                cycles: 0,
+                reg_stat: vec![RegisterStatus{ busy: false, reorder: None }; 32],
+                regs: [0i32;32],
+                instruction_queue: VecDeque::new(),
+                front_end: None,
+                fetch_queue: VecDeque::new(),
+                fetch_resume_at: 0,
+                rename_mode: RenameMode::Rob,
+                prf: None,
+                rs: vec![],
+                rob: vec![],
+                exec_units: vec![],
+                memory: Memory::init(),
+                cache: None,
+                store_buffer: VecDeque::new(),
+                store_buffer_size: STORE_BUFFER_SIZE,
+                pc: 0,
+                stage_latency: StageLatency::default(),
+                select_policy: SelectPolicy::OldestFirst,
+                select_rng: ChaCha12Rng::seed_from_u64(0),
+                cdb_count: None,
+                cdb_arbitration: CdbArbitration::OldestFirst,
+                cdb_round_robin: 0,
+                cycle_limit: None,
+                watchdog: DEFAULT_WATCHDOG_CYCLES,
+                idle_cycles: 0,
+                halt: None,
+                stats: Stats::new()
+            }
+        }
     }
 
     pub fn done(&self) -> bool {
-        self.done
+        self.state.done
+    }
+
+    /// 设置流水线各阶段之间的最小延迟
+    pub fn set_stage_latency(&mut self, latency: StageLatency) {
+        self.state.stage_latency = latency;
+    }
+
+    /// 设置取指与译码前端，None 表示理想前端
+    pub fn set_front_end(&mut self, front_end: Option<FrontEnd>) {
+        self.state.front_end = front_end;
+    }
+
+    /// 设置寄存器重命名方式，需要在运行之前设置
+    pub fn set_rename_mode(&mut self, mode: RenameMode) {
+        self.state.rename_mode = mode;
+        self.state.prf = match mode {
+            RenameMode::PhysRegFile(count) => Some(PhysRegFile::new(count, &self.state.regs)),
+            _ => None
+        };
+    }
+
+    /// 设置数据 cache，None 表示不模拟 cache
+    pub fn set_cache(&mut self, cache: Option<CacheHierarchy>) {
+        self.state.cache = cache;
+    }
+
+    /// 设置内存的字节序
+    pub fn set_endian(&mut self, endian: Endian) {
+        self.state.memory.set_endian(endian);
+    }
+
+    /// 设置非对齐访存的处理方式
+    pub fn set_misaligned(&mut self, misaligned: Misaligned) {
+        self.state.memory.set_misaligned(misaligned);
+    }
+
+    /// 设置内存映射，None 表示所有地址都可以读写
+    pub fn set_memory_map(&mut self, map: Option<MemoryMap>) {
+        self.state.memory.set_map(map);
+    }
+
+    /// 在 base 处挂载内存映射的设备，设备的 store 在提交时才执行
+    pub fn attach_device(&mut self, base: u32, device: Box<dyn Device>) -> Result<(), String> {
+        self.state.memory.attach(base, device)
+    }
+
+    /// 设备（控制台）的输出
+    pub fn output(&self) -> String {
+        self.state.memory.output()
+    }
+
+    /// 设置 store buffer 的项数，store buffer 已满时 store 无法提交
+    pub fn set_store_buffer_size(&mut self, size: usize) {
+        assert!(size > 0, "store buffer size must be positive");
+        self.state.store_buffer_size = size;
+    }
+
+    /// 是否为没有 ROB 的经典 Tomasulo 模式
+    fn classic(&self) -> bool {
+        self.state.rename_mode == RenameMode::Classic
+    }
+
+    /// 设置选择就绪指令的策略
+    pub fn set_select_policy(&mut self, policy: SelectPolicy) {
+        self.state.select_policy = policy;
+        if let SelectPolicy::Random(seed) = policy {
+            self.state.select_rng = ChaCha12Rng::seed_from_u64(seed);
+        }
+    }
+
+    /// 设置 CDB 的数量，None 表示每周期所有执行完毕的结果都可以广播
+    pub fn set_cdb_count(&mut self, count: Option<usize>) {
+        assert_ne!(count, Some(0), "at least one CDB is required");
+        self.state.cdb_count = count;
+    }
+
+    /// 设置 CDB 仲裁策略
+    pub fn set_cdb_arbitration(&mut self, arbitration: CdbArbitration) {
+        self.state.cdb_arbitration = arbitration;
+    }
+
+    /// 设置周期上限，达到上限时停止运行
+    pub fn set_cycle_limit(&mut self, limit: Option<usize>) {
+        self.state.cycle_limit = limit;
+    }
+
+    /// 设置连续多少个周期没有进展即认为死锁并停止运行，
+    /// 只有 CDB 广播、提交以及 store buffer 写入内存算作进展
+    pub fn set_watchdog(&mut self, cycles: usize) {
+        self.state.watchdog = cycles;
+    }
+
+    /// 被强制停止时的诊断信息，正常结束时为 None
+    pub fn halted(&self) -> Option<&Halt> {
+        self.state.halt.as_ref()
+    }
+
+    /// 保存检查点（JSON 格式），包括体系结构状态以及 ROB、保留站、寄存器状态、
+    /// 执行单元、cache 等微体系结构状态，不包括追踪文件与挂载的设备
+    pub fn checkpoint(&self) -> String {
+        let checkpoint = Checkpoint {
+            state: &self.state,
+            stats_accumulators: self.state.stats.accumulators()
+        };
+        serde_json::to_string(&checkpoint).unwrap()
+    }
+
+    /// 从检查点恢复，之后的运行与不中断地运行相同；当前挂载的设备保持不变，
+    /// 设备自身的状态（如控制台输出、退出码）不会恢复
+    pub fn restore(&mut self, checkpoint: &str) -> Result<(), String> {
+        let checkpoint: Checkpoint<TomasuloState> = serde_json::from_str(checkpoint).map_err(|err| format!("err: {}", err))?;
+        let mut state = checkpoint.state;
+        state.stats.restore_accumulators(checkpoint.stats_accumulators);
+        let old = std::mem::replace(&mut self.state, state);
+        let snapshot = std::mem::replace(&mut self.state.memory, old.memory);
+        self.state.memory.restore(snapshot);
+        self.events = CycleEvents::default();
+        Ok(())
+    }
+
+    /// 获取当前的统计信息
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.state.stats.clone();
+        stats.halted = self.state.halt.as_ref().map(|halt| format!("{}: {}", halt.reason, halt.oldest));
+        stats.exit_code = self.state.memory.exit_code();
+        if let Some(cache) = &self.state.cache {
+            stats.cache = cache.stats();
+            stats.avg_memory_latency = Some(cache.avg_latency());
+            stats.mshr = cache.mshr_stats();
+        }
+        stats.finish(self.state.cycles);
+        stats
+    }
+
+    /// 运行一个周期，返回该周期内发生的事件
+    pub fn step(&mut self) -> CycleEvents {
+        self.events = CycleEvents::default();
+        let store_buffer_len = self.state.store_buffer.len();
+        self.mult_issue(ISSUE_WIDTH);
+        self.check_watchdog(store_buffer_len);
+        self.events.cycle = self.state.cycles;
+        self.events.done = self.state.done;
+        self.events.clone()
+    }
+
+    /// 检查周期上限以及是否长时间没有进展；忙碌的执行单元或者非空的 store buffer 可能永远不会完成，
+    /// 因此只有 CDB 广播、提交或者 store buffer 缩短（周期开始时为 store_buffer_len 项）才算作进展
+    fn check_watchdog(&mut self, store_buffer_len: usize) {
+        if self.state.done {
+            return
+        }
+        let progress = !self.events.written_back.is_empty() || !self.events.committed.is_empty()
+            || self.state.store_buffer.len() < store_buffer_len;
+        self.state.idle_cycles = if progress { 0 } else { self.state.idle_cycles + 1 };
+        let reason = if self.state.idle_cycles >= self.state.watchdog {
+            Some(HaltReason::Deadlock(self.state.idle_cycles))
+        }else {
+            self.state.cycle_limit.filter(|limit| self.state.cycles >= *limit).map(HaltReason::CycleLimit)
+        };
+        if let Some(reason) = reason {
+            self.raise(reason);
+        }
+    }
+
+    /// 停止运行并记录原因与当时的状态
+    fn raise(&mut self, reason: HaltReason) {
+        self.state.halt = Some(Halt{
+            reason,
+            cycle: self.state.cycles,
+            oldest: self.describe_oldest(),
+            dump: self.dump()
+        });
+        self.state.done = true;
+    }
+
+    /// 描述 ROB 标签对应的指令
+    fn describe_tag(&self, tag: usize) -> String {
+        if self.classic() {
+            return match self.state.rs.get(tag) {
+                Some(rs) => format!("RS#{} (pc {} {})", tag, rs.inner.pc, opt(&rs.inner.inst)),
+                None => format!("unknown tag {}", tag)
+            }
+        }
+        match self.find_producer(tag) {
+            Some(rob_index) => format!(
+                "ROB#{} (pc {} {})", 
+                rob_index, opt(&self.state.rob[rob_index].inner.pc), opt(&self.state.rob[rob_index].inner.inst)
+            ),
+            None => format!("unknown tag {}", tag)
+        }
+    }
+
+    /// 描述最老的未提交指令以及它在等待什么
+    fn describe_oldest(&self) -> String {
+        let pending = || match self.pending_insts().next() {
+            Some(inst) => format!("pc {} {} waiting to issue", self.state.pc, inst),
+            None => "none".to_string()
+        };
+        let (inst, rs_index) = if self.classic() {
+            // 经典 Tomasulo 模式下没有 ROB，最老的指令为序号最小的保留站
+            match self.state.rs.iter().enumerate().filter(|(_, rs)| rs.busy).min_by_key(|(_, rs)| rs.inner.pc) {
+                Some((rs_index, rs)) => (format!("pc {} {}", rs.inner.pc, opt(&rs.inner.inst)), Some(rs_index)),
+                None => return pending()
+            }
+        }else {
+            let head = &self.state.rob[0];
+            if !head.busy {
+                return pending()
+            }
+            let inst = format!("pc {} {}", opt(&head.inner.pc), opt(&head.inner.inst));
+            if head.ready {
+                return format!("{} ready to commit", inst)
+            }
+            (inst, self.state.rs.iter().position(|rs| rs.busy && rs.inner.dest == Some(head.index)))
+        };
+        let waiting = match rs_index.and_then(|rs_index| self.rs_state(rs_index).map(|state| (rs_index, state))) {
+            Some((rs_index, RsState::WaitingQj)) => format!("waiting on Qj = {}", self.describe_tag(self.state.rs[rs_index].inner.rs_index.unwrap())),
+            Some((rs_index, RsState::WaitingQk)) => format!("waiting on Qk = {}", self.describe_tag(self.state.rs[rs_index].inner.rt_index.unwrap())),
+            Some((rs_index, RsState::WaitingExecUnit)) => {
+                if self.state.rs[rs_index].exec {
+                    format!("RS#{} marked executing but holds no exec unit", rs_index)
+                }else {
+                    format!("RS#{} waiting for a free {} exec unit", rs_index, self.state.rs[rs_index].rs_type.name())
+                }
+            },
+            Some((rs_index, RsState::Executing)) => format!("executing from RS#{}", rs_index),
+            Some((rs_index, RsState::WaitingCdb)) => format!("RS#{} finished, waiting for a CDB", rs_index),
+            Some((rs_index, RsState::Latched)) => format!("RS#{} waiting for the pipeline latch", rs_index),
+            None => "not held by any reservation station".to_string()
+        };
+        format!("{} {}", inst, waiting)
+    }
+
+    /// 将 ROB 与保留站的内容转储为字符串
+    fn dump(&self) -> String {
+        let mut dump = String::from("ROB:\n");
+        for (i, rob) in self.state.rob.iter().enumerate() {
+            dump.push_str(&format!("{:<4}{}\n", i, rob));
+        }
+        dump.push_str("RS:\n");
+        for (i, rs) in self.state.rs.iter().enumerate() {
+            dump.push_str(&format!("{:<4}{}\n", i, rs));
+        }
+        dump.push_str("Exec units:\n");
+        for (i, unit) in self.state.exec_units.iter().enumerate() {
+            dump.push_str(&format!("{:<4}{}\n", i, unit));
+        }
+        dump
+    }
+
+    /// 一直运行直到 pred 返回 true 或者运行结束，返回运行的周期数
+    pub fn run_until<F>(&mut self, mut pred: F) -> usize
+        where F: FnMut(&Self) -> bool
+    {
+        let start = self.state.cycles;
+        while !self.done() && !pred(self) {
+            self.step();
+        }
+        self.state.cycles - start
+    }
+
+    /// 最多运行 n 个周期，返回实际运行的周期数
+    pub fn run_for(&mut self, n: usize) -> usize {
+        let end = self.state.cycles + n;
+        self.run_until(|cpu| cpu.cycles() >= end)
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.state.cycles
+    }
+
+    /// 下一条将发射的指令序号
+    pub fn pc(&self) -> usize {
+        self.state.pc
+    }
+
+    /// 设置下一条发射的指令序号，用于从快进后的状态开始详细模拟
+    pub(crate) fn set_pc(&mut self, pc: usize) {
+        self.state.pc = pc;
+    }
+
+    /// 已经提交的指令数
+    pub fn committed(&self) -> usize {
+        self.state.stats.committed
+    }
+
+    /// 上一周期发生的事件
+    pub fn last_events(&self) -> &CycleEvents {
+        &self.events
+    }
+
+    pub fn regs(&self) -> &[i32; 32] {
+        &self.state.regs
+    }
+
+    pub fn read_memory(&self, addr: u32) -> i32 {
+        self.state.memory.read(addr)
+    }
+
+    pub fn instruction_queue(&self) -> &VecDeque<Instruction> {
+        &self.state.instruction_queue
+    }
+
+    /// 已提交、尚未写入内存的 store
+    pub fn store_buffer(&self) -> &VecDeque<StoreEntry> {
+        &self.state.store_buffer
+    }
+
+    /// 取指队列，(指令, 译码完成的周期数)
+    pub fn fetch_queue(&self) -> &VecDeque<(Instruction, usize)> {
+        &self.state.fetch_queue
+    }
+
+    /// 按程序顺序排列的尚未发射的指令，包括取指队列与指令队列
+    pub fn pending_insts(&self) -> impl Iterator<Item = &Instruction> {
+        self.state.fetch_queue.iter().map(|(inst, _)| inst).chain(self.state.instruction_queue.iter())
+    }
+
+    pub fn reservation_stations(&self) -> &[ReservedStation] {
+        &self.state.rs
+    }
+
+    pub fn reorder_buffer(&self) -> &[ReorderBuffer] {
+        &self.state.rob
+    }
+
+    pub fn register_status(&self) -> &[RegisterStatus] {
+        &self.state.reg_stat
+    }
+
+    /// 物理寄存器堆，以 ROB 标签重命名时为 None
+    pub fn physical_registers(&self) -> Option<&PhysRegFile> {
+        self.state.prf.as_ref()
+    }
+
+    /// 数据 cache
+    pub fn cache(&self) -> Option<&CacheHierarchy> {
+        self.state.cache.as_ref()
+    }
+
+    pub fn exec_units(&self) -> &[ExecUint] {
+        &self.state.exec_units
     }
 
 
     /// 添加保留站
-    fn add_rs(&mut self, rs_type: ResStationType, count: usize) {
+    pub fn add_rs(&mut self, rs_type: ResStationType, count: usize) {
         for _ in 0..count {
-            self.rs.push(
+            self.state.rs.push(
                 ReservedStation { 
-                    rs_type: rs_type,
+                    rs_type,
                     busy: false,
                     exec: false,
                     inner: ResStationInner{
@@ -222,7 +1029,10 @@ impl<'a> TomasuloCpu<'a> {
                         rs_value: None,
                         rt_index: None,
                         rt_value: None,
-                        dest: None
+                        dest: None,
+                        pc: 0,
+                        issued_at: 0,
+                        woken_at: 0
                     }
                 }
             )
@@ -230,32 +1040,49 @@ impl<'a> TomasuloCpu<'a> {
     }
 
     /// 添加 ROB
-    fn add_rob(&mut self, count: usize) {
+    pub fn add_rob(&mut self, count: usize) {
         for _ in 0..count {
-            self.rob.push(ReorderBuffer::init())
+            self.state.rob.push(ReorderBuffer::init())
         }
     } 
 
     /// 添加执行单元
-    fn add_exec_unit(&mut self, rs_type: ResStationType, count: usize) {
+    pub fn add_exec_unit(&mut self, rs_type: ResStationType, count: usize) {
         for _ in 0..count {
-            self.exec_units.push(ExecUint {
+            self.state.exec_units.push(ExecUint {
                 busy: false,
-                rs_type: rs_type,
+                rs_type,
                 cycles: 0,
                 rs_index: 0
             });
         }
+        self.state.stats.add_units(rs_type.name(), count);
     }
 
     fn find_reorder(&self, addr: usize) -> Option<usize> {
-        self.rob.iter().position(|item| item.index == addr) 
+        self.state.rob.iter().position(|item| item.index == addr) 
+    }
+
+    /// 查找产生 tag 结果的 ROB，物理寄存器堆模式下 tag 为物理寄存器编号
+    fn find_producer(&self, tag: usize) -> Option<usize> {
+        match self.state.prf {
+            Some(_) => self.state.rob.iter().position(|item| item.busy && item.inner.phys.map(|(phys, _)| phys) == Some(tag)),
+            None => self.find_reorder(tag)
+        }
+    }
+
+    /// 保留站中指令的结果在 CDB 上广播时使用的标签
+    fn result_tag(&self, rs_index: usize) -> usize {
+        let dest = self.state.rs[rs_index].inner.dest.unwrap();
+        self.find_reorder(dest)
+            .and_then(|rob_index| self.state.rob[rob_index].inner.phys)
+            .map_or(dest, |(phys, _)| phys)
     }
 
     /// 发现空闲的执行单元
     fn find_empty_exec_unit(&self, rs_type: ResStationType) -> Option<usize> {
-        for i in 0..self.exec_units.len() {
-            if !self.exec_units[i].busy && self.exec_units[i].rs_type == rs_type {
+        for i in 0..self.state.exec_units.len() {
+            if !self.state.exec_units[i].busy && self.state.exec_units[i].rs_type == rs_type {
                 return Some(i)
             }
         }
@@ -264,23 +1091,40 @@ impl<'a> TomasuloCpu<'a> {
 
     /// 发射操作数，即将操作数写入到保留站中
     fn issue_op(&mut self, reg_index: usize, rs: usize, op: usize) {
+        // 物理寄存器堆模式下直接通过别名表读取操作数
+        if let Some(prf) = &self.state.prf {
+            let (value, tag) = match prf.read(reg_index) {
+                Ok(value) => (Some(value), None),
+                Err(phys) => (None, Some(phys))
+            };
+            let rs = &mut self.state.rs[rs];
+            if op == 1 {
+                rs.inner.rs_value = value;
+                rs.inner.rs_index = tag;
+            }else if op == 2 {
+                rs.inner.rt_value = value;
+                rs.inner.rt_index = tag;
+            }
+            return
+        }
         // 发射操作数
-        let reg_stat = &self.reg_stat;
+        let reg_stat = &self.state.reg_stat;
         // 如果操作数目前的状态是 busy 表示当前操作数不在寄存器中
         // 而将要被前面的指令写回或者在 ROB 中
         if reg_stat[reg_index].busy  {
             // 获取 reorder_addr 地址的值
             let reorder_addr = reg_stat[reg_index].reorder.unwrap();
-            let reorder_index = self.find_reorder(reorder_addr).unwrap();
-            let rs = &mut self.rs[rs];
-            if self.rob[reorder_index].ready {
+            // 经典 Tomasulo 模式下没有 ROB，结果只能等待 CDB 广播
+            let reorder_index = if self.classic() { None } else { self.find_reorder(reorder_addr) };
+            let rs = &mut self.state.rs[rs];
+            if let Some(reorder_index) = reorder_index.filter(|reorder_index| self.state.rob[*reorder_index].ready) {
                 // 在 ROB 中已经将该寄存器的值计算完成，但仍然没有 commit
                 // 此时直接进行赋值即可
                 if op == 1 {
-                    rs.inner.rs_value = Some(self.rob[reorder_index].inner.value.unwrap());
+                    rs.inner.rs_value = Some(self.state.rob[reorder_index].inner.value.unwrap());
                     rs.inner.rs_index = None;
                 }else if op == 2 {
-                    rs.inner.rt_value = Some(self.rob[reorder_index].inner.value.unwrap());
+                    rs.inner.rt_value = Some(self.state.rob[reorder_index].inner.value.unwrap());
                     rs.inner.rt_index = None;
                 }
             }else{
@@ -292,26 +1136,42 @@ impl<'a> TomasuloCpu<'a> {
                 }
             }
         }else{
-            let rs = &mut self.rs[rs];
+            let rs = &mut self.state.rs[rs];
             // 目前操作数在寄存器堆中
             if op == 1 {
-                rs.inner.rs_value = Some(self.regs[reg_index]);
+                rs.inner.rs_value = Some(self.state.regs[reg_index]);
                 rs.inner.rs_index = None;
             }else if op == 2 {
-                rs.inner.rt_value = Some(self.regs[reg_index]);
+                rs.inner.rt_value = Some(self.state.regs[reg_index]);
                 rs.inner.rt_index = None;
             }
         }
     }
 
+    /// 重命名目标寄存器，物理寄存器堆模式下分配新的物理寄存器，
+    /// 否则将寄存器状态指向标签 tag（ROB 或者保留站）
+    fn rename_dest(&mut self, reg: usize, rob: Option<usize>, tag: usize) {
+        match (&mut self.state.prf, rob) {
+            (Some(prf), Some(rob)) => self.state.rob[rob].inner.phys = prf.rename(reg),
+            _ => {
+                self.state.reg_stat[reg].reorder = Some(tag);
+                self.state.reg_stat[reg].busy = true;
+            }
+        }
+    }
+
     /// 查看是否能发射
     /// FP 操作需要看保留站是否有空闲, Load/Store 需要看 Buffer 是否有空闲
-    fn can_issue(&self, rs_type: ResStationType) -> Option<(usize, usize)> {
-        for i in 0..self.rs.len() {
-            if !self.rs[i].busy && self.rs[i].rs_type == rs_type {
-                for j in 0..self.rob.len() {
-                    if !self.rob[j].busy {
-                        return Some((i, j))
+    /// 经典 Tomasulo 模式下不需要 ROB，返回的 ROB 为 None
+    fn can_issue(&self, rs_type: ResStationType) -> Option<(usize, Option<usize>)> {
+        for i in 0..self.state.rs.len() {
+            if !self.state.rs[i].busy && self.state.rs[i].rs_type == rs_type {
+                if self.classic() {
+                    return Some((i, None))
+                }
+                for j in 0..self.state.rob.len() {
+                    if !self.state.rob[j].busy {
+                        return Some((i, Some(j)))
                     }
                 }
             }
@@ -320,309 +1180,686 @@ impl<'a> TomasuloCpu<'a> {
     }
 
     pub fn load_can_exec(&self, rs_index: usize) -> bool {
-        if self.rs[rs_index].inner.rs_index.is_none() && self.rs[rs_index].busy && !self.rs[rs_index].exec {
-            // let rob_index = self.rs[rs_index].inner.dest.unwrap();
-            // let rob_index = self.find_reorder(rob_index).unwrap();
-            // let rob = &self.rob[rob_index];
-            // for i in 0..rob_index-1 {
-            //     if let Some(item_inst) = self.rob[i].inner.inst {
-            //         if let Instruction::Sd(_, _, _) = item_inst {
-
-            //         }
-            //     }
-            // }
-            return true
+        let rs = &self.state.rs[rs_index];
+        if rs.inner.rs_index.is_none() && rs.busy && !rs.exec {
+            // 更早的 store 都已经算出地址与数据之后 load 才能执行，
+            // 之后 load 可以从 ROB 或者 store buffer 中得到这些 store 的数据
+            return !self.state.rs.iter().any(|older| {
+                older.busy && matches!(older.inner.inst, Some(Instruction::Sd(..))) && older.inner.pc < rs.inner.pc
+            })
          }
         false
-        // true
-    }
-
-    /// 发射指令，每周期发射一条指令
-    pub(crate) fn issue(&mut self) {
-        if let Some(inst) = self.instruction_queue.pop_front() {
-            let rs_type: ResStationType = inst.into();
-            if let Some((rs, rob)) = self.can_issue(rs_type) {
-                match rs_type {
-                    // 浮点数运算操作
-                    ResStationType::AddSub | ResStationType::MulDiv => {
-                        match inst {
-                            Instruction::Add(op) | Instruction::Sub(op) | Instruction::Mul(op) | Instruction::Div(op) => {
-                                let r1 = op.operand1 as usize;
-                                let r2 = op.operand2 as usize;
-                                let rd = op.target as usize;
-                                // 发射操作数
-                                self.issue_op(r1, rs, 1);
-                                self.issue_op(r2, rs, 2);
-                                
-                                let rs = &mut self.rs[rs];
-                                rs.inner.inst = Some(inst);
-                                // 设置 ROB 地址
-                                rs.inner.dest = Some(self.rob[rob].index);
-                                rs.busy = true;
-                                // 设置目标寄存器状态
-                                self.reg_stat[rd].reorder = Some(self.rob[rob].index);
-                                self.reg_stat[rd].busy = true;
-                                // 设置 ROB 的信息
-                                self.rob[rob].inner.dest = Some(rd);
-                                self.rob[rob].busy = true;
-                                self.rob[rob].ready = false;
-                                self.rob[rob].inner.inst = Some(inst);
-                            },
-
-                            _ => {}
-                        }
-                    },
-                    ResStationType::LoadStore => {
-                        match inst {
-                            Instruction::Ld(reg1, reg2, imm) => {
-                                // 加载指令需要将 reg2 寄存器的内容 + imm 的值作为地址
-                                // 并从内存中取出来存储到 reg1 中
-                                // 首先需要发射操作数 2, 当等到其 Qj = 0 的时候才可以拿出来执行
-                                self.issue_op(reg2, rs, 1);
-                                let rs = &mut self.rs[rs];
-        
-                                rs.inner.inst = Some(inst);
-                                rs.inner.address = Some(imm);
-                                rs.busy = true;
-                                rs.inner.dest = Some(self.rob[rob].index);
-
-                                self.reg_stat[reg1].reorder = Some(self.rob[rob].index);
-                                self.reg_stat[reg1].busy = true;
-
-                                self.rob[rob].inner.dest = Some(reg1);
-                                self.rob[rob].inner.inst = Some(inst);
-                                self.rob[rob].busy = true;
-                                self.rob[rob].ready = false;
-                            },
-
-                            Instruction::Sd(reg1, reg2, imm) => {
-
-                            },
-                            _ => { 
-                                println!("[Error] inst: {:?}", inst);
-                                panic!("Error instruction") 
-                            }
-                        }
-                    },
-
-                    ResStationType::JUMP => {
-                        if let Instruction::Jump(r1, r2) = inst {
-                             // 发射操作数
-                             self.issue_op(r1, rs, 1);
-                             self.issue_op(r2, rs, 2);
-                             
-                             let rs = &mut self.rs[rs];
-                             rs.inner.inst = Some(inst);
-                             // 设置 ROB 地址
-                             rs.inner.dest = Some(self.rob[rob].index);
-                             rs.busy = true;
-                             // 由于没有目标寄存器，因此不需要设置目标寄存器状态
-                             // 设置 ROB 的信息
-                             self.rob[rob].busy = true;
-                             self.rob[rob].ready = false;
-                             self.rob[rob].inner.inst = Some(inst);
-                        } 
-                    }
+    }
+
+    /// 经典 Tomasulo 模式下没有 ROB 保证访存顺序，store 在写回时就进入 store buffer，
+    /// 因此要等更早的 load 与 store 都写回、且 store buffer 有空位之后才能执行
+    fn classic_store_can_exec(&self, rs_index: usize) -> bool {
+        let pc = self.state.rs[rs_index].inner.pc;
+        self.state.store_buffer.len() < self.state.store_buffer_size && !self.state.rs.iter().any(|older| {
+            older.busy && matches!(older.inner.inst, Some(Instruction::Ld(..) | Instruction::Sd(..))) && older.inner.pc < pc
+        })
+    }
+
+    /// load 读取的值：从内存读出后，依次用 store buffer 以及 ROB 中更早的、
+    /// 尚未写入内存的 store 覆盖重叠的字节
+    fn load_value(&self, addr: u32, pc: usize, width: Width) -> i32 {
+        // 设备寄存器不经过转发，直接读取设备
+        if self.state.memory.device(addr).is_some() {
+            return self.state.memory.load(addr, width).unwrap_or_default()
+        }
+        let mut bytes = self.state.memory.read_bytes(addr, width.bytes());
+        let buffered = self.state.store_buffer.iter().map(|entry| (entry.address, entry.width, entry.value));
+        let in_flight = self.state.rob.iter()
+            .filter(|rob| rob.busy && rob.ready && rob.inner.pc.is_some_and(|store_pc| store_pc < pc))
+            .filter_map(|rob| match rob.inner.inst {
+                Some(Instruction::Sd(_, _, _, width)) => Some((rob.inner.address?, width.into(), rob.inner.value?)),
+                _ => None
+            });
+        for (store_addr, store_width, value) in buffered.chain(in_flight) {
+            for (i, byte) in self.state.memory.encode(value, store_width).into_iter().enumerate() {
+                let offset = store_addr.wrapping_add(i as u32).wrapping_sub(addr) as usize;
+                if offset < bytes.len() {
+                    bytes[offset] = byte;
                 }
+            }
+        }
+        self.state.memory.decode(&bytes, width)
+    }
+
+    /// store buffer 按顺序将头部的 store 写入内存，同一时刻只有一个 store 在写
+    fn drain_store_buffer(&mut self) {
+        if let Some(entry) = self.state.store_buffer.front() {
+            if entry.done_at.is_some_and(|done_at| self.state.cycles >= done_at) {
+                self.state.memory.store(entry.address, entry.width, entry.value).expect("store checked at execute");
+                self.state.store_buffer.pop_front();
+            }
+        }
+        if let Some(entry) = self.state.store_buffer.front_mut() {
+            if entry.done_at.is_none() {
+                // 有 cache 时写入的延迟由写策略决定，没有空闲的 MSHR 时下周期重试
+                let accesses = self.state.memory.check(entry.address, entry.width, true).unwrap_or(1);
+                let latency = match &mut self.state.cache {
+                    // 设备寄存器不可缓存，只有经典 Tomasulo 模式下设备的 store 才会进入 store buffer
+                    Some(_) if self.state.memory.device(entry.address).is_some() => Some(LOAD_CYCLES),
+                    Some(cache) => Self::access_cache(cache, entry.address, entry.width, accesses, self.state.cycles, true),
+                    None => Some(LOAD_CYCLES * accesses)
+                };
+                entry.done_at = latency.map(|latency| self.state.cycles + latency);
+            }
+        }
+    }
+
+    /// 取指，将指令从指令队列取到取指队列中
+    fn fetch(&mut self) {
+        let Some(front_end) = self.state.front_end else { return };
+        if self.state.cycles < self.state.fetch_resume_at {
+            self.state.stats.record_fetch_stall(false);
+            return
+        }
+        for _ in 0..front_end.fetch_width {
+            if self.state.fetch_queue.len() >= front_end.queue_depth {
+                self.state.stats.record_fetch_stall(true);
+                break;
+            }
+            let Some(inst) = self.state.instruction_queue.pop_front() else { break };
+            self.events.fetched.push((self.state.pc + self.state.fetch_queue.len(), inst));
+            self.state.fetch_queue.push_back((inst, self.state.cycles + front_end.decode_delay));
+            if let Instruction::Jump(_, _) = inst {
+                // 跳转指令被预测为跳转，重定向之前无法继续取指
+                self.state.fetch_resume_at = self.state.cycles + 1 + front_end.redirect_penalty;
+                self.state.stats.record_redirect();
+                break;
+            }
+        }
+    }
+
+    /// 取出下一条可以发射的指令，没有时返回发射槽停顿的原因
+    fn next_inst(&mut self) -> Result<Instruction, IssueSlot> {
+        if self.state.front_end.is_none() {
+            return self.state.instruction_queue.pop_front().ok_or(IssueSlot::QueueEmpty)
+        }
+        match self.state.fetch_queue.front() {
+            Some((inst, ready_at)) if *ready_at <= self.state.cycles => {
+                let inst = *inst;
+                self.state.fetch_queue.pop_front();
+                Ok(inst)
+            },
+            // 指令仍在译码
+            Some(_) => Err(IssueSlot::FetchStarved),
+            None if self.state.instruction_queue.is_empty() => Err(IssueSlot::QueueEmpty),
+            None if self.state.cycles < self.state.fetch_resume_at => Err(IssueSlot::BranchBlocked),
+            None => Err(IssueSlot::FetchStarved)
+        }
+    }
+
+    /// 发射失败时将指令放回队列的顶部
+    fn return_inst(&mut self, inst: Instruction) {
+        if self.state.front_end.is_none() {
+            self.state.instruction_queue.push_front(inst);
+        }else {
+            self.state.fetch_queue.push_front((inst, self.state.cycles));
+        }
+    }
+
+    /// 发射指令，每次发射一条指令，返回该发射槽的结果
+    pub(crate) fn issue(&mut self) -> IssueSlot {
+        let inst = match self.next_inst() {
+            Ok(inst) => inst,
+            Err(slot) => return slot
+        };
+        if let Instruction::Invalid = inst {
+            // 非法指令不发射，更早的指令都提交之后停止运行，指令留在队列的头部
+            self.return_inst(inst);
+            let drained = if self.classic() {
+                self.state.rs.iter().all(|rs| !rs.busy)
             }else {
-                // 当目前没有足够的保留站时需要将其 push 到队列的顶部
-                self.instruction_queue.push_front(inst);
+                !self.state.rob[0].busy
+            };
+            if drained {
+                self.flush_store_buffer();
+                self.raise(HaltReason::InvalidInstruction(self.state.pc));
             }
-        }   
+            return IssueSlot::QueueEmpty
+        }
+        let rs_type: ResStationType = inst.into();
+        // 物理寄存器堆模式下需要有空闲的物理寄存器
+        let free_list_empty = inst.dest().is_some() && self.state.prf.as_ref().is_some_and(|prf| prf.free_count() == 0);
+        if free_list_empty {
+            self.return_inst(inst);
+            return IssueSlot::FreeListEmpty
+        }
+        let Some((rs, rob)) = self.can_issue(rs_type) else {
+            // 当目前没有足够的保留站时需要将其 push 到队列的顶部
+            self.return_inst(inst);
+            return if !self.classic() && self.state.rob.iter().all(|rob| rob.busy) {
+                IssueSlot::RobFull
+            }else {
+                IssueSlot::RsFull(rs_type)
+            }
+        };
+        match inst {
+            // 浮点数运算操作
+            Instruction::Add(op) | Instruction::Sub(op) | Instruction::Mul(op) | Instruction::Div(op) => {
+                // 发射操作数
+                self.issue_op(op.operand1, rs, 1);
+                self.issue_op(op.operand2, rs, 2);
+            },
+            Instruction::Ld(_, reg2, imm, _) => {
+                // 加载指令需要将 reg2 寄存器的内容 + imm 的值作为地址
+                // 并从内存中取出来存储到 reg1 中
+                // 首先需要发射操作数 2, 当等到其 Qj = 0 的时候才可以拿出来执行
+                self.issue_op(reg2, rs, 1);
+                self.state.rs[rs].inner.address = Some(imm);
+            },
+            Instruction::Jump(r1, r2) => {
+                // 发射操作数，由于没有目标寄存器，因此不需要设置目标寄存器状态
+                self.issue_op(r1, rs, 1);
+                self.issue_op(r2, rs, 2);
+            },
+            Instruction::Sd(reg1, reg2, imm, _) => {
+                // store 以 reg2 + imm 为地址，reg1 的值作为写入的数据，
+                // 在提交时进入 store buffer
+                self.issue_op(reg2, rs, 1);
+                self.issue_op(reg1, rs, 2);
+                self.state.rs[rs].inner.address = Some(imm);
+            },
+            // 非法指令在上面已经停止运行
+            Instruction::Invalid => unreachable!()
+        }
+        // 设置 ROB 的信息，经典 Tomasulo 模式下以保留站编号作为标签
+        let tag = match rob {
+            Some(rob) => {
+                let rob = &mut self.state.rob[rob];
+                rob.busy = true;
+                rob.ready = false;
+                rob.inner.inst = Some(inst);
+                rob.inner.dest = inst.dest();
+                rob.inner.pc = Some(self.state.pc);
+                rob.index
+            },
+            None => rs
+        };
+        let res_station = &mut self.state.rs[rs];
+        res_station.busy = true;
+        res_station.inner.inst = Some(inst);
+        res_station.inner.dest = Some(tag);
+        res_station.inner.pc = self.state.pc;
+        res_station.inner.issued_at = self.state.cycles;
+        res_station.inner.woken_at = 0;
+        // 设置目标寄存器状态
+        if let Some(rd) = inst.dest() {
+            self.rename_dest(rd, rob, tag);
+        }
+        self.events.issued.push((self.state.pc, inst));
+        self.state.pc += 1;
+        IssueSlot::Issued
     }
 
-    /// 执行指令
-    pub(crate) fn exec(&mut self) {
-        // 遍历保留站检查有哪些写指令可以开始执行
-        for rs_index in 0..self.rs.len() {
-            match self.rs[rs_index].rs_type {
-                ResStationType::AddSub | ResStationType::MulDiv => {
-                    if self.rs[rs_index].inner.rs_index.is_none() && self.rs[rs_index].inner.rt_index.is_none() && self.rs[rs_index].busy && !self.rs[rs_index].exec {
-                        self.rs[rs_index].exec = true;
-                        let inst = self.rs[rs_index].inner.inst.unwrap();
-                        let rs_type: ResStationType = inst.into();
-                        if let Some(exec_unit_index) = self.find_empty_exec_unit(rs_type) {
-                            self.exec_units[exec_unit_index].busy = true;
-                            match inst {
-                                Instruction::Add(_) => { self.exec_units[exec_unit_index].cycles = ADD_CYCLES },
-                                Instruction::Sub(_) => { self.exec_units[exec_unit_index].cycles = SUB_CYCLES },
-                                Instruction::Mul(_) => { self.exec_units[exec_unit_index].cycles = MUL_CYCLES },
-                                Instruction::Div(_) => { self.exec_units[exec_unit_index].cycles = DIV_CYCLES },
-                                _ => {}
-                            }
-                            // 执行单元获取保留站的索引
-                            self.exec_units[exec_unit_index].rs_index = rs_index;
-                        }
-                    }
-                },
+    /// 停止运行前将 store buffer 中已经完成的 store 全部写入内存
+    fn flush_store_buffer(&mut self) {
+        for entry in std::mem::take(&mut self.state.store_buffer) {
+            self.state.memory.store(entry.address, entry.width, entry.value).expect("store checked at execute");
+        }
+    }
 
-                ResStationType::LoadStore => {
-                    if let Some(inst) = self.rs[rs_index].inner.inst {
-                        match inst {
-                            Instruction::Ld(_, _, _) => {
-                                 if self.load_can_exec(rs_index) {
-                                     // 如果可以执行加载指令
-                                     self.rs[rs_index].exec = true;
-                                     let rs_type = self.rs[rs_index].rs_type;
-                                     if let Some(exec_unit_index) = self.find_empty_exec_unit(rs_type) {
-                                        // 执行单元获取保留站的索引
-                                        self.exec_units[exec_unit_index].cycles = LOAD_CYCLES;
-                                        self.exec_units[exec_unit_index].rs_index = rs_index;
-                                        self.exec_units[exec_unit_index].busy = true;
-                                     }
-                                    
-                                 }
-                            },
-                            Instruction::Sd(_, _, _) => {
-
-                            },
-                            _ => {panic!("get error instruction")}
-                        }
-                    }
-                }
+    /// 记录保留站中的指令开始执行
+    fn record_dispatch(&mut self, rs_index: usize) {
+        let rs = &self.state.rs[rs_index];
+        self.events.dispatched.push((rs.inner.pc, rs.inner.inst.unwrap()));
+    }
 
-                ResStationType::JUMP => {
-                    if self.rs[rs_index].inner.rs_index.is_none() && self.rs[rs_index].inner.rt_index.is_none() && self.rs[rs_index].busy && !self.rs[rs_index].exec {
-                        self.rs[rs_index].exec = true;
-                        let inst = self.rs[rs_index].inner.inst.unwrap();
-                        let rs_type: ResStationType = inst.into();
-                        if let Some(exec_unit_index) = self.find_empty_exec_unit(rs_type) {
-                            self.exec_units[exec_unit_index].busy = true;
-                            // 执行阶段什么都不做，独占一个周期
-                            self.exec_units[exec_unit_index].cycles = JUMP_CYCLES;
-                            // 执行单元获取保留站的索引
-                            self.exec_units[exec_unit_index].rs_index = rs_index;
-                        }
-                    }
-                }
+    /// 保留站中的指令是否可以开始执行，即操作数已经就绪、
+    /// 已经经过流水线寄存器的最小延迟且还未被派遣
+    fn rs_ready(&self, rs_index: usize) -> bool {
+        self.operands_ready(rs_index) && self.latch_passed(rs_index)
+    }
+
+    /// 保留站是否已经经过发射与唤醒后的最小延迟
+    fn latch_passed(&self, rs_index: usize) -> bool {
+        let inner = &self.state.rs[rs_index].inner;
+        self.state.cycles >= inner.issued_at + self.state.stage_latency.issue_to_dispatch
+            && self.state.cycles >= inner.woken_at + self.state.stage_latency.wakeup_to_select
+    }
+
+    /// 保留站中指令的操作数是否已经就绪且还未被派遣
+    fn operands_ready(&self, rs_index: usize) -> bool {
+        let rs = &self.state.rs[rs_index];
+        if !rs.busy || rs.exec {
+            return false
+        }
+        match rs.inner.inst {
+            Some(Instruction::Ld(..)) => self.load_can_exec(rs_index),
+            Some(Instruction::Sd(..)) if self.classic() => {
+                rs.inner.rs_index.is_none() && rs.inner.rt_index.is_none() && self.classic_store_can_exec(rs_index)
+            },
+            Some(_) => rs.inner.rs_index.is_none() && rs.inner.rt_index.is_none(),
+            None => false
+        }
+    }
+
+    /// 保留站中指令的年龄，即其在程序中的序号，越小越老
+    fn rs_age(&self, rs_index: usize) -> usize {
+        self.state.rs[rs_index].inner.pc
+    }
+
+    /// 依赖 tag 结果的最长后续指令链的执行周期数
+    fn dependent_height(&self, tag: usize, depth: usize) -> usize {
+        // 保留站中的依赖不会成环，深度限制只是保险
+        if depth > self.state.rs.len() {
+            return 0
+        }
+        self.state.rs.iter().enumerate()
+            .filter(|(_, rs)| rs.busy && (rs.inner.rs_index == Some(tag) || rs.inner.rt_index == Some(tag)))
+            .map(|(rs_index, rs)| exec_cycles(&rs.inner.inst.unwrap()) + self.dependent_height(self.result_tag(rs_index), depth + 1))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 按照选择策略对就绪的保留站进行排序
+    fn select(&mut self, ready: &mut [usize]) {
+        match self.state.select_policy {
+            SelectPolicy::OldestFirst => ready.sort_by_key(|rs_index| self.rs_age(*rs_index)),
+            SelectPolicy::Position => ready.sort(),
+            SelectPolicy::Random(_) => ready.shuffle(&mut self.state.select_rng),
+            SelectPolicy::CriticalPath => ready.sort_by_key(|rs_index| {
+                let height = self.dependent_height(self.result_tag(*rs_index), 0);
+                (std::cmp::Reverse(height), self.rs_age(*rs_index))
+            })
+        }
+    }
+
+    /// 将保留站中的指令派遣到执行单元
+    fn dispatch(&mut self, rs_index: usize, exec_unit_index: usize, cycles: usize) {
+        self.state.rs[rs_index].exec = true;
+        let unit = &mut self.state.exec_units[exec_unit_index];
+        unit.busy = true;
+        unit.cycles = cycles;
+        // 执行单元获取保留站的索引
+        unit.rs_index = rs_index;
+        self.record_dispatch(rs_index);
+    }
+
+    /// 保留站中指令的执行周期数，有 cache 时 load 的延迟由访问 cache 的结果决定，
+    /// 没有空闲的 MSHR 时返回 None
+    fn exec_latency(&mut self, rs_index: usize) -> Option<usize> {
+        let inner = &self.state.rs[rs_index].inner;
+        let inst = inner.inst.unwrap();
+        let Instruction::Ld(_, _, _, width) = inst else {
+            return Some(exec_cycles(&inst))
+        };
+        // 拆分的非对齐访存需要访问两次，异常的访问不访问 cache，在写回时处理
+        let addr = (inner.address.unwrap() as i32 + inner.rs_value.unwrap()) as u32;
+        let Ok(accesses) = self.state.memory.check(addr, width, false) else {
+            return Some(exec_cycles(&inst))
+        };
+        match &mut self.state.cache {
+            // 设备寄存器不可缓存
+            Some(_) if self.state.memory.device(addr).is_some() => Some(exec_cycles(&inst)),
+            Some(cache) => Self::access_cache(cache, addr, width, accesses, self.state.cycles, false),
+            None => Some(exec_cycles(&inst) * accesses)
+        }
+    }
+
+    /// 访问 cache，拆分的访问依次访问首尾两个字节所在的位置，返回总的延迟
+    fn access_cache(cache: &mut CacheHierarchy, addr: u32, width: Width, accesses: usize, now: usize, write: bool) -> Option<usize> {
+        let last = addr.wrapping_add(width.bytes() as u32 - 1);
+        let parts = if accesses > 1 { vec![addr, last] } else { vec![addr] };
+        cache.access_parts(&parts, now, write)
+    }
+
+    /// 执行指令：唤醒操作数就绪的保留站，按选择策略为其分配空闲的执行单元，
+    /// 只有真正获得执行单元的保留站才会被标记为正在执行
+    pub(crate) fn exec(&mut self) {
+        let mut ready: Vec<usize> = (0..self.state.rs.len()).filter(|rs_index| self.rs_ready(*rs_index)).collect();
+        self.select(&mut ready);
+        for rs_index in ready {
+            match self.find_empty_exec_unit(self.state.rs[rs_index].rs_type) {
+                Some(exec_unit_index) => match self.exec_latency(rs_index) {
+                    Some(cycles) => self.dispatch(rs_index, exec_unit_index, cycles),
+                    None => self.state.stats.stalls.mshr_full += 1
+                },
+                None => self.state.stats.stalls.no_free_exec_unit += 1
             }
         }
     }
 
     /// 将结果写到 CDB 总线并进行广播
     pub(crate) fn write_result(&mut self) {
-        for i in 0..self.exec_units.len() {
-            if self.exec_units[i].busy && self.exec_units[i].cycles > 0 {
-                self.exec_units[i].cycles -= 1;
-            }
-            if self.exec_units[i].cycles == 0 && self.exec_units[i].busy {
-                // 当执行所需周期为 0 时，需要计算结果并将其送到 CDB 总线上
-                let rs_index = self.exec_units[i].rs_index;
-                let res_station = &mut self.rs[rs_index];
-                let inst = res_station.inner.inst.unwrap();
-                let mut res: i32 = 0;
-                match inst {
-                    Instruction::Add(_) => { res = if let Some(add_res) = res_station.inner.rs_value.unwrap().checked_add(res_station.inner.rt_value.unwrap()){ add_res }else{ 0 } },
-                    Instruction::Sub(_) => { res = if let Some(sub_res) = res_station.inner.rs_value.unwrap().checked_sub(res_station.inner.rt_value.unwrap()){ sub_res }else{ 0 } },
-                    Instruction::Mul(_) => { res = if let Some(mul_res) = res_station.inner.rs_value.unwrap().checked_mul(res_station.inner.rt_value.unwrap()){ mul_res }else{ 0 } },
-                    Instruction::Div(_) => { res = if let Some(div_res) = res_station.inner.rs_value.unwrap().checked_div(res_station.inner.rt_value.unwrap()){ div_res }else { 0 } }
-                    Instruction::Ld(_, _, _) => {
-                        // load 指令的两步直接在一步做了
-                        let addr = (res_station.inner.address.unwrap() as i32 + res_station.inner.rs_value.unwrap()) as u32;
-                        res_station.inner.address = Some(addr);
-                        res = self.memory.read(addr);
-                    },
-                    Instruction::Jump(_, _) => {
-                        // 什么都不做
-                    }
-                    _ => { panic!("[Error] invalid instruction"); }
-                }
-                
-                res_station.busy = false;
-                res_station.exec = false;
-                // 获取到 reorder 的地址
-                let dest = res_station.inner.dest.unwrap();
-                // 获取到 reorder 的地址
-                let rob_index = self.find_reorder(dest).expect(format!("Invalid dest: {}", dest).as_str());
-                // 将依赖于该寄存器的保留站的操作数写入
-                // 模拟的是 CDB 的广播
-                for rs_item in self.rs.iter_mut() {
-                    if rs_item.inner.rs_index == Some(dest) {
-                        rs_item.inner.rs_value = Some(res);
-                        rs_item.inner.rs_index = None;
-                    }
-                    if rs_item.inner.rt_index == Some(dest) {
-                        rs_item.inner.rt_value = Some(res);
-                        rs_item.inner.rt_index = None;
-                    }
+        // 执行完毕、等待 CDB 的执行单元
+        let mut finished = vec![];
+        for i in 0..self.state.exec_units.len() {
+            if self.state.exec_units[i].busy && self.state.exec_units[i].cycles > 0 {
+                self.state.exec_units[i].cycles -= 1;
+            }
+            if self.state.exec_units[i].cycles == 0 && self.state.exec_units[i].busy {
+                finished.push(i);
+            }
+        }
+        // CDB 数量有限时进行仲裁，没有抢到 CDB 的执行单元保持占用，下周期继续竞争
+        self.arbitrate(&mut finished);
+        let width = self.state.cdb_count.unwrap_or(finished.len()).min(finished.len());
+        let waiting = finished.split_off(width);
+        self.state.stats.record_cdb(finished.len(), waiting.len(), self.state.cdb_count);
+        for i in finished {
+            self.broadcast(i);
+        }
+    }
+
+    /// 按照仲裁策略对等待 CDB 的执行单元进行排序
+    fn arbitrate(&mut self, finished: &mut [usize]) {
+        match self.state.cdb_arbitration {
+            CdbArbitration::OldestFirst => finished.sort_by_key(|i| self.rs_age(self.state.exec_units[*i].rs_index)),
+            CdbArbitration::UnitOrder => {},
+            CdbArbitration::RoundRobin => {
+                let n = self.state.exec_units.len();
+                let start = self.state.cdb_round_robin;
+                finished.sort_by_key(|i| (i + n - start) % n);
+                self.state.cdb_round_robin = (start + 1) % n.max(1);
+            }
+        }
+    }
+
+    /// 计算执行单元 i 的结果并在 CDB 上广播
+    fn broadcast(&mut self, i: usize) {
+        // 当执行所需周期为 0 时，需要计算结果并将其送到 CDB 总线上
+        let rs_index = self.state.exec_units[i].rs_index;
+        let res_station = &mut self.state.rs[rs_index];
+        let inst = res_station.inner.inst.unwrap();
+        let mut res: i32 = 0;
+        let mut fault = None;
+        match inst {
+            Instruction::Add(_) => { res = res_station.inner.rs_value.unwrap().checked_add(res_station.inner.rt_value.unwrap()).unwrap_or_default() },
+            Instruction::Sub(_) => { res = res_station.inner.rs_value.unwrap().checked_sub(res_station.inner.rt_value.unwrap()).unwrap_or_default() },
+            Instruction::Mul(_) => { res = res_station.inner.rs_value.unwrap().checked_mul(res_station.inner.rt_value.unwrap()).unwrap_or_default() },
+            Instruction::Div(_) => { res = res_station.inner.rs_value.unwrap().checked_div(res_station.inner.rt_value.unwrap()).unwrap_or_default() }
+            Instruction::Ld(..) | Instruction::Sd(..) => {
+                // 计算地址并检查访问是否合法，load 的值在下面读取，store 的结果为写入的数据
+                let width = inst.width().unwrap();
+                let addr = (res_station.inner.address.unwrap() as i32 + res_station.inner.rs_value.unwrap()) as u32;
+                res_station.inner.address = Some(addr);
+                fault = self.state.memory.check(addr, width, matches!(inst, Instruction::Sd(..))).err();
+                if let Instruction::Sd(..) = inst {
+                    res = res_station.inner.rt_value.unwrap();
                 }
-                // 将 ROB ready 设置为 true，表示可以进行提交了
-                self.rob[rob_index].ready = true;
-                self.rob[rob_index].inner.value = Some(res);
-                // 将执行单元设置为空闲
-                self.exec_units[i].busy = false;
+            },
+            Instruction::Jump(_, _) => {
+                // 什么都不做
+            }
+            _ => { panic!("[Error] invalid instruction"); }
+        }
+        
+        res_station.busy = false;
+        res_station.exec = false;
+        // 获取到 reorder 的地址
+        let dest = res_station.inner.dest.unwrap();
+        if let (Instruction::Ld(_, _, _, width), None) = (inst, fault) {
+            res = self.load_value(self.state.rs[rs_index].inner.address.unwrap(), self.state.rs[rs_index].inner.pc, width);
+        }
+        // 经典 Tomasulo 模式下没有 ROB，直接以保留站编号作为标签广播，异常立即停止运行
+        if self.classic() {
+            if let Some(fault) = fault {
+                self.flush_store_buffer();
+                self.raise(HaltReason::Fault(self.state.rs[rs_index].inner.pc, fault));
+                return
+            }
+            self.broadcast_classic(i, dest, inst, res);
+            return
+        }
+        // 获取到 reorder 的地址
+        let rob_index = self.find_reorder(dest).unwrap_or_else(|| panic!("Invalid dest: {}", dest));
+        // 物理寄存器堆模式下结果写入物理寄存器，并以物理寄存器编号作为标签广播
+        let tag = match (&mut self.state.prf, self.state.rob[rob_index].inner.phys) {
+            (Some(prf), Some((phys, _))) => {
+                prf.write(phys, res);
+                phys
+            },
+            _ => dest
+        };
+        self.wake_up(tag, res);
+        // 将 ROB ready 设置为 true，表示可以进行提交了
+        self.state.rob[rob_index].ready = true;
+        if self.state.prf.is_none() || matches!(inst, Instruction::Sd(..)) {
+            self.state.rob[rob_index].inner.value = Some(res);
+        }
+        self.state.rob[rob_index].inner.written_at = self.state.cycles;
+        self.state.rob[rob_index].inner.address = self.state.rs[rs_index].inner.address;
+        self.state.rob[rob_index].inner.fault = fault;
+        self.events.written_back.push(CdbBroadcast{ tag, pc: self.state.rob[rob_index].inner.pc.unwrap(), inst, value: res });
+        // 将执行单元设置为空闲
+        self.state.exec_units[i].busy = false;
+    }
+
+    /// 将依赖于标签 tag 的保留站的操作数写入，模拟的是 CDB 的广播
+    fn wake_up(&mut self, tag: usize, res: i32) {
+        for rs_item in self.state.rs.iter_mut() {
+            if rs_item.inner.rs_index == Some(tag) {
+                rs_item.inner.rs_value = Some(res);
+                rs_item.inner.rs_index = None;
+                rs_item.inner.woken_at = self.state.cycles;
+            }
+            if rs_item.inner.rt_index == Some(tag) {
+                rs_item.inner.rt_value = Some(res);
+                rs_item.inner.rt_index = None;
+                rs_item.inner.woken_at = self.state.cycles;
+            }
+        }
+    }
+
+    /// 经典 Tomasulo 模式下的写回：结果由 CDB 直接写入等待该保留站的寄存器，
+    /// 指令随即完成，不再按程序顺序提交
+    fn broadcast_classic(&mut self, i: usize, tag: usize, inst: Instruction, res: i32) {
+        self.wake_up(tag, res);
+        let rs = &self.state.rs[tag];
+        // 没有提交阶段，store 在写回时进入 store buffer，按顺序经过 cache 写入内存
+        if let Instruction::Sd(_, _, _, width) = inst {
+            self.state.store_buffer.push_back(StoreEntry{
+                pc: rs.inner.pc,
+                address: rs.inner.address.unwrap(),
+                width: width.into(),
+                value: res,
+                done_at: None
+            });
+        }
+        let mut retired = Retired{ pc: rs.inner.pc, inst, reg_write: None, address: rs.inner.address };
+        // 只有寄存器状态仍然指向该保留站时才写入，否则之后还有指令会写该寄存器
+        for reg in 0..self.state.regs.len() {
+            if self.state.reg_stat[reg].busy && self.state.reg_stat[reg].reorder == Some(tag) {
+                self.state.regs[reg] = res;
+                self.state.reg_stat[reg].busy = false;
+                self.state.reg_stat[reg].reorder = None;
+                retired.reg_write = Some((reg, res));
             }
         }
+        self.state.stats.record_commit(&inst);
+        self.events.written_back.push(CdbBroadcast{ tag, pc: retired.pc, inst, value: res });
+        self.events.committed.push(retired);
+        self.state.exec_units[i].busy = false;
+        self.trace_regs();
+    }
+
+    /// 将寄存器堆写入追踪文件
+    fn trace_regs(&mut self) {
+        self.trace(format_regs(&self.state.regs));
     }
 
     /// 提交指令
     pub(crate) fn commit(&mut self) {
+        let issued_all = self.state.instruction_queue.is_empty() && self.state.fetch_queue.is_empty();
+        // 经典 Tomasulo 模式下没有 ROB，指令在写回时即完成，所有保留站空闲即结束
+        if self.classic() {
+            self.state.done = (issued_all && self.state.rs.iter().all(|rs| !rs.busy) && self.state.store_buffer.is_empty())
+                || self.state.memory.exit_code().is_some();
+            return
+        }
         // 检查 ROB 头部的指令是否能被提交
-        if self.instruction_queue.len() == 0 && !self.rob[0].busy {
-            self.done = true;
+        if issued_all && !self.state.rob[0].busy && self.state.store_buffer.is_empty() {
+            self.state.done = true;
         }else{
-            while self.rob[0].ready && self.rob[0].busy {
-                let rob_head = &self.rob[0];
+            while self.state.rob[0].ready && self.state.rob[0].busy
+                && self.state.cycles >= self.state.rob[0].inner.written_at + self.state.stage_latency.writeback_to_commit {
+                let rob_head = &self.state.rob[0];
                 let inst = rob_head.inner.inst.unwrap();
+                // 精确异常：更早的指令都已提交，已提交的 store 写入内存后停止运行
+                if let Some(fault) = rob_head.inner.fault {
+                    let pc = rob_head.inner.pc.unwrap();
+                    self.flush_store_buffer();
+                    self.raise(HaltReason::Fault(pc, fault));
+                    return
+                }
+                if let Instruction::Sd(_, _, _, width) = inst {
+                    let address = rob_head.inner.address.unwrap();
+                    if self.state.memory.device(address).is_some() {
+                        // 设备的 store 等更早的 store 都写入内存后在提交时执行，不经过 store buffer
+                        if !self.state.store_buffer.is_empty() {
+                            break
+                        }
+                        self.state.memory.store(address, width.into(), rob_head.inner.value.unwrap()).expect("store checked at execute");
+                    }else {
+                        // store 提交时进入 store buffer，store buffer 已满时停止提交
+                        if self.state.store_buffer.len() >= self.state.store_buffer_size {
+                            self.state.stats.stalls.store_buffer_full += 1;
+                            break
+                        }
+                        self.state.store_buffer.push_back(StoreEntry{
+                            pc: rob_head.inner.pc.unwrap(),
+                            address,
+                            width: width.into(),
+                            value: rob_head.inner.value.unwrap(),
+                            done_at: None
+                        });
+                    }
+                }
+                self.state.stats.record_commit(&inst);
                 let rs_type: ResStationType = inst.into();
+                let mut retired = Retired{ 
+                    pc: rob_head.inner.pc.unwrap(), 
+                    inst, 
+                    reg_write: None, 
+                    address: rob_head.inner.address 
+                };
                 // 获取写回寄存器的编号
                 if let Some(dest) = rob_head.inner.dest {
                     match rs_type {
                         ResStationType::AddSub | ResStationType::MulDiv | ResStationType::LoadStore => {
-                            // 浮点数操作直接将计算的值写回到寄存器堆中
-                            self.regs[dest] = rob_head.inner.value.unwrap();
+                            // 浮点数操作直接将计算的值写回到寄存器堆中，
+                            // 物理寄存器堆模式下则更新提交的别名表并释放之前的物理寄存器
+                            self.state.regs[dest] = match (&mut self.state.prf, rob_head.inner.phys) {
+                                (Some(prf), Some((phys, prev))) => {
+                                    prf.retire(dest, phys, prev);
+                                    prf.value(phys)
+                                },
+                                _ => rob_head.inner.value.unwrap()
+                            };
+                            retired.reg_write = Some((dest, self.state.regs[dest]));
                         },
                         _ => {}
                     }
                     // 将寄存器状态由 busy 修改为 free
-                    if self.reg_stat[dest].reorder == Some(rob_head.index) && self.reg_stat[dest].busy {
-                        self.reg_stat[dest].busy = false;
-                        self.reg_stat[dest].reorder = None;
+                    if self.state.reg_stat[dest].reorder == Some(rob_head.index) && self.state.reg_stat[dest].busy {
+                        self.state.reg_stat[dest].busy = false;
+                        self.state.reg_stat[dest].reorder = None;
                     }
                 }
+                self.events.committed.push(retired);
                 // 将 ROB 从 reorder 队列中 pop 出来
-                self.rob.remove(0);
+                self.state.rob.remove(0);
                 // 重新 push 一个初始化的 ROB
-                self.rob.push(ReorderBuffer::init());
-                let mut info: String = String::new();
-                for (index, reg) in self.regs.iter().enumerate() {
-                    info.push_str(format!("reg{}: {}; ", index, reg).as_str());
+                self.state.rob.push(ReorderBuffer::init());
+                self.trace_regs();
+                // 写停机寄存器后，之后的指令都不再提交
+                if self.state.memory.exit_code().is_some() {
+                    self.state.done = true;
+                    return
                 }
-                self.trace(info);
-                
+
             }
         }
         
     }
 
-    /// 在一周期内所执行的操作
-    /// 包括发射、执行、写结果、提交
+    // 在一周期内所执行的操作
+    // 包括发射、执行、写结果、提交
     // pub(crate) fn single_cycle(&mut self) {
     //     // 将结果写到 CDB 总线并进行广播
     //     self.write_result();
     //     // 进行指令提交
     //     self.commit();
     //     // 将周期添加 1
-    //     self.cycles += 1;
+    //     self.state.cycles += 1;
     //     // 进行指令发射
     //     self.issue();
     //     // 检查保留站开始执行指令
     //     self.exec();
     // }
 
+    /// 在一周期内所执行的操作，每周期最多发射 issue_nums 条指令
     pub(crate) fn mult_issue(&mut self, issue_nums: usize) {
-         // 将结果写到 CDB 总线并进行广播
-         self.write_result();
-         // 进行指令提交
-         self.commit();
-        // 将周期添加 1
-        self.cycles += 1;
-        // 进行多次指令发射
-        for _ in 0..issue_nums {
-            self.issue();
+        // 将周期添加 1，本周期内各阶段看到的周期数相同
+        self.state.cycles += 1;
+        self.state.memory.set_cycle(self.state.cycles);
+        // 将结果写到 CDB 总线并进行广播
+        self.write_result();
+        // 进行指令提交
+        self.commit();
+        // 已提交的 store 写入内存
+        self.drain_store_buffer();
+        // 取指
+        self.fetch();
+        // 进行多次指令发射，一旦发射失败本周期剩余的发射槽也因同样的原因停顿
+        for slot in 0..issue_nums {
+            let res = self.issue();
+            self.events.issue_slots.push(res);
+            if res == IssueSlot::Issued {
+                self.state.stats.record_issue(res, 1);
+            }else {
+                self.state.stats.record_issue(res, issue_nums - slot);
+                self.events.issue_slots.extend(std::iter::repeat_n(res, issue_nums - slot - 1));
+                break;
+            }
         }
         // 检查保留站开始执行指令
         self.exec();
+        // 记录本周期的占用情况
+        self.sample_stats();
+    }
+
+    /// 每周期采样 ROB、保留站以及执行单元的占用情况
+    fn sample_stats(&mut self) {
+        let rob_busy = self.state.rob.iter().filter(|rob| rob.busy).count();
+        let rs_busy = self.state.rs.iter().filter(|rs| rs.busy).count();
+        self.state.stats.sample_occupancy(rob_busy, rs_busy);
+        if let Some(cache) = &mut self.state.cache {
+            cache.sample(self.state.cycles);
+        }
+        for rs_type in [ResStationType::AddSub, ResStationType::MulDiv, ResStationType::LoadStore, ResStationType::JUMP] {
+            let busy = self.state.exec_units.iter().filter(|unit| unit.busy && unit.rs_type == rs_type).count();
+            self.state.stats.record_unit_busy(rs_type.name(), busy);
+        }
+        // 对每个被占用的保留站进行分类
+        for i in 0..self.state.rs.len() {
+            if let Some(state) = self.rs_state(i) {
+                self.state.stats.record_rs_state(self.state.rs[i].rs_type, state);
+            }
+        }
+    }
+
+    /// 保留站当前所处的状态，空闲时返回 None
+    fn rs_state(&self, rs_index: usize) -> Option<RsState> {
+        let rs = &self.state.rs[rs_index];
+        if !rs.busy {
+            return None
+        }
+        if let Some(unit) = self.state.exec_units.iter().find(|unit| unit.busy && unit.rs_index == rs_index) {
+            if unit.cycles == 0 { Some(RsState::WaitingCdb) } else { Some(RsState::Executing) }
+        }else if rs.inner.rs_index.is_some() {
+            Some(RsState::WaitingQj)
+        }else if rs.inner.rt_index.is_some() {
+            Some(RsState::WaitingQk)
+        }else if !rs.exec && !self.latch_passed(rs_index) {
+            Some(RsState::Latched)
+        }else {
+            Some(RsState::WaitingExecUnit)
+        }
     }
 
 }
\ No newline at end of file
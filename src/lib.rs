@@ -4,17 +4,1336 @@ pub mod trace;
 
 
 
-// #[cfg(test)]
-// mod test {
-//     use crate::cpu::{TomasuloCpu, Cpu, Instruction, Operand};
-
-
-//     #[test]
-//     fn run_test() {
-//         let mut cpu = TomasuloCpu::new();
-//         cpu.add_inst(Instruction::Add(Operand::new(1, 2, 3)));
-//         cpu.add_inst(Instruction::Sub(Operand::new(1, 2, 3)));
-//         cpu.run();
-//     }
-// }
+#[cfg(test)]
+mod test {
+    use crate::cpu::{ TomasuloCpu, SingleCycleCpu, ScoreboardCpu, PipelinedCpu, Forwarding, Cpu, Instruction, Operand, ResStationType, SelectPolicy, CdbArbitration, HaltReason, StageLatency, FrontEnd, RenameMode, Cache, CacheConfig, CacheHierarchy, Replacement, WritePolicy, Width, StoreWidth, Endian, Misaligned, MemoryMap, MemFault, Console, CycleCounter, ArchState, StateDiff, Sampling };
+    use crate::cpu::device::{ standard_devices, CONSOLE_BASE, CYCLE_COUNTER_BASE, HALT_BASE };
+    use crate::trace::Trace;
+    use crate::parser::{ Parser, InitMode };
+
+    /// 每类只有一个执行单元，但保留站多于执行单元
+    fn contended_cpu(trace: &mut Trace) -> TomasuloCpu<'_> {
+        let mut cpu = TomasuloCpu::new_empty(trace);
+        cpu.add_rs(ResStationType::AddSub, 3);
+        cpu.add_rs(ResStationType::MulDiv, 3);
+        cpu.add_rs(ResStationType::LoadStore, 3);
+        cpu.add_rs(ResStationType::JUMP, 2);
+        cpu.add_rob(8);
+        cpu.add_exec_unit(ResStationType::AddSub, 1);
+        cpu.add_exec_unit(ResStationType::MulDiv, 1);
+        cpu.add_exec_unit(ResStationType::LoadStore, 1);
+        cpu.add_exec_unit(ResStationType::JUMP, 1);
+        cpu
+    }
+
+    fn mixed_program() -> Vec<Instruction> {
+        vec![
+            Instruction::Ld(1, 0, 0x0, Width::Word),
+            Instruction::Ld(2, 0, 0x4, Width::Word),
+            Instruction::Mul(Operand::new(3, 1, 2)),
+            Instruction::Mul(Operand::new(4, 2, 2)),
+            Instruction::Div(Operand::new(5, 4, 1)),
+            Instruction::Add(Operand::new(6, 3, 4)),
+            Instruction::Sub(Operand::new(7, 6, 5)),
+            Instruction::Add(Operand::new(8, 1, 1)),
+            Instruction::Jump(7, 8),
+            Instruction::Mul(Operand::new(9, 7, 8)),
+            Instruction::Add(Operand::new(1, 9, 2)),
+        ]
+    }
+
+    #[test]
+    fn unit_contention_completes() {
+        let mut trace = Trace::new("traces/test_unit_contention.txt");
+        let mut cpu = contended_cpu(&mut trace);
+        cpu.set_regs(2, 3);
+        cpu.set_regs(3, 4);
+        for target in [1, 4, 5] {
+            cpu.add_inst(Instruction::Mul(Operand::new(target, 2, 3)));
+        }
+        let stats = cpu.run();
+        assert!(stats.halted.is_none(), "{:?}", stats.halted);
+        assert_eq!(stats.committed, 3);
+        assert_eq!(cpu.regs()[1], 12);
+        assert_eq!(cpu.regs()[4], 12);
+        assert_eq!(cpu.regs()[5], 12);
+        // 三条乘法只能在唯一的乘除法单元上串行执行
+        assert!(stats.cycles >= 3 * 12);
+        assert!(stats.stalls.no_free_exec_unit > 0);
+    }
+
+    /// 四条相互独立的加法共用一个加减法单元，第四条指令会复用第一条释放的保留站
+    fn dispatch_order(policy: SelectPolicy, trace: &mut Trace) -> Vec<usize> {
+        let program: Vec<Instruction> = (1..5).map(|target| Instruction::Add(Operand::new(target, 10, 11))).collect();
+        program_dispatch_order(policy, &program, trace)
+    }
+
+    /// 在每类只有一个执行单元的 CPU 上运行程序，返回指令派遣的顺序
+    fn program_dispatch_order(policy: SelectPolicy, program: &[Instruction], trace: &mut Trace) -> Vec<usize> {
+        let mut cpu = contended_cpu(trace);
+        cpu.set_select_policy(policy);
+        for inst in program {
+            cpu.add_inst(*inst);
+        }
+        let mut dispatched = vec![];
+        while !cpu.done() {
+            dispatched.extend(cpu.step().dispatched.into_iter().map(|(pc, _)| pc));
+        }
+        dispatched
+    }
+
+    #[test]
+    fn unit_contention_dispatches_oldest_first() {
+        let mut trace = Trace::new("traces/test_oldest_first.txt");
+        assert_eq!(dispatch_order(SelectPolicy::OldestFirst, &mut trace), vec![0, 1, 2, 3]);
+        let mut trace = Trace::new("traces/test_position.txt");
+        assert_eq!(dispatch_order(SelectPolicy::Position, &mut trace), vec![0, 3, 1, 2]);
+    }
+
+    #[test]
+    fn select_policy_from_str() {
+        assert_eq!("oldest".parse::<SelectPolicy>().unwrap(), SelectPolicy::OldestFirst);
+        assert_eq!("position".parse::<SelectPolicy>().unwrap(), SelectPolicy::Position);
+        assert_eq!("critical".parse::<SelectPolicy>().unwrap(), SelectPolicy::CriticalPath);
+        assert_eq!("random".parse::<SelectPolicy>().unwrap(), SelectPolicy::Random(0));
+        assert_eq!("random:42".parse::<SelectPolicy>().unwrap(), SelectPolicy::Random(42));
+        assert!("random:x".parse::<SelectPolicy>().is_err());
+        assert!("random42".parse::<SelectPolicy>().is_err());
+        assert!("youngest".parse::<SelectPolicy>().is_err());
+    }
+
+    #[test]
+    fn random_select_is_reproducible() {
+        let mut trace = Trace::new("traces/test_random_select.txt");
+        let order = dispatch_order(SelectPolicy::Random(1), &mut trace);
+        let mut sorted = order.clone();
+        sorted.sort();
+        assert_eq!(sorted, vec![0, 1, 2, 3]);
+        // 相同的种子得到相同的顺序，不同的种子得到不同的顺序
+        assert_eq!(dispatch_order(SelectPolicy::Random(1), &mut trace), order);
+        assert_ne!(dispatch_order(SelectPolicy::Random(2), &mut trace), order);
+    }
+
+    #[test]
+    fn critical_path_dispatches_longest_chain_first() {
+        // 两条加法同时就绪，第二条的结果还要经过两条乘法
+        let program = vec![
+            Instruction::Add(Operand::new(1, 10, 11)),
+            Instruction::Add(Operand::new(2, 10, 11)),
+            Instruction::Mul(Operand::new(3, 2, 2)),
+            Instruction::Mul(Operand::new(4, 3, 3)),
+        ];
+        let mut trace = Trace::new("traces/test_critical_path.txt");
+        assert_eq!(program_dispatch_order(SelectPolicy::OldestFirst, &program, &mut trace), vec![0, 1, 2, 3]);
+        // 第二条加法先执行，第一条乘法随后在乘除法单元上执行，不必等第一条加法
+        assert_eq!(program_dispatch_order(SelectPolicy::CriticalPath, &program, &mut trace), vec![1, 2, 0, 3]);
+    }
+
+    #[test]
+    fn stats_count_commits_and_stalls() {
+        // 只有三个加减法保留站，第四条加法要等前三条写回后才能发射
+        let mut trace = Trace::new("traces/test_stats.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        for target in 1..=4 {
+            cpu.add_inst(Instruction::Add(Operand::new(target, 0, 0)));
+        }
+        let stats = cpu.run();
+        // 前三条加法在第 1 个周期发射、第 3 个周期提交，第四条在第 3 个周期发射、第 5 个周期提交
+        assert_eq!(stats.cycles, 6);
+        assert_eq!(stats.committed, 4);
+        assert_eq!(stats.opcode_counts["ADD"], 4);
+        assert_eq!(stats.ipc, 4.0 / 6.0);
+        assert_eq!(stats.cpi, 6.0 / 4.0);
+        // 第 1、2 个周期第四条加法因保留站已满而停顿
+        assert_eq!(stats.stalls.rs_full["AddSub"], 2);
+        assert_eq!(stats.stalls.rob_full, 0);
+        assert_eq!(stats.stalls.operand_wait, 0);
+        assert_eq!(stats.stalls.no_free_exec_unit, 0);
+        // 每条加法占用执行单元 2 个周期，共 3 个加减法单元
+        assert_eq!(stats.unit_utilization["AddSub"], 8.0 / 18.0);
+        // ROB 在第 1、2 个周期各有 3 项，第 3、4 个周期各有 1 项
+        assert_eq!(stats.avg_rob_occupancy, 8.0 / 6.0);
+    }
+
+    #[test]
+    fn top_bottleneck_names_full_structure() {
+        // 八条互不相关的除法只有两个乘除法保留站可用
+        let mut trace = Trace::new("traces/test_bottleneck_rs.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_regs(2, 1);
+        for target in 3..=10 {
+            cpu.add_inst(Instruction::Div(Operand::new(target, 2, 2)));
+        }
+        let stats = cpu.run();
+        assert_eq!(stats.committed, 8);
+        assert_eq!(stats.top_bottleneck().unwrap().0, "backend: RS MulDiv");
+        assert!(stats.stalls.rs_full["MulDiv"] > 0);
+        assert_eq!(stats.stalls.rob_full, 0);
+        // 除法提交之前，后面的加法执行完毕也只能留在 ROB 中
+        let mut trace = Trace::new("traces/test_bottleneck_rob.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_regs(2, 1);
+        cpu.add_inst(Instruction::Div(Operand::new(1, 2, 2)));
+        for target in 3..=12 {
+            cpu.add_inst(Instruction::Add(Operand::new(target, 2, 2)));
+        }
+        let stats = cpu.run();
+        assert_eq!(stats.committed, 11);
+        assert_eq!(stats.top_bottleneck().unwrap().0, "backend: ROB");
+        // 排空阶段的空发射槽不参与排名
+        assert!(stats.slots.queue_empty > 0);
+        assert!(stats.breakdown.iter().all(|(name, _)| !name.contains("queue empty")));
+        assert!(stats.stalls.rob_full > stats.stalls.rs_full["AddSub"]);
+    }
+
+    #[test]
+    fn single_cdb_serializes_writeback() {
+        let mut trace = Trace::new("traces/test_single_cdb.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_cdb_count(Some(1));
+        cpu.set_regs(2, 3);
+        cpu.set_regs(3, 4);
+        // 两条加法在同一周期执行完毕，但只有一条 CDB
+        cpu.add_inst(Instruction::Add(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Add(Operand::new(4, 2, 3)));
+        let mut written_back = vec![];
+        while !cpu.done() {
+            written_back.push(cpu.step().written_back.len());
+        }
+        let stats = cpu.stats();
+        assert!(written_back.iter().all(|count| *count <= 1));
+        assert_eq!(stats.stalls.cdb_wait, 1);
+        assert_eq!(stats.cdb_broadcasts, 2);
+        assert_eq!(cpu.regs()[1], 7);
+        assert_eq!(cpu.regs()[4], 7);
+    }
+
+    /// 乘法、加法与 load 在第 13 个周期同时执行完毕，只有一条 CDB，返回此后写回的指令序号
+    fn cdb_winners(arbitration: CdbArbitration, trace: &mut Trace) -> Vec<usize> {
+        let mut cpu = TomasuloCpu::new_empty(trace);
+        cpu.add_rs(ResStationType::AddSub, 6);
+        cpu.add_rs(ResStationType::MulDiv, 1);
+        cpu.add_rs(ResStationType::LoadStore, 1);
+        cpu.add_rob(8);
+        // 执行单元编号：加减法 0，跳转 1，乘除法 2，访存 3、4
+        cpu.add_exec_unit(ResStationType::AddSub, 1);
+        cpu.add_exec_unit(ResStationType::JUMP, 1);
+        cpu.add_exec_unit(ResStationType::MulDiv, 1);
+        cpu.add_exec_unit(ResStationType::LoadStore, 2);
+        cpu.set_cdb_count(Some(1));
+        cpu.set_cdb_arbitration(arbitration);
+        cpu.set_regs(2, 4);
+        cpu.add_inst(Instruction::Mul(Operand::new(1, 2, 2)));
+        for _ in 0..5 {
+            cpu.add_inst(Instruction::Add(Operand::new(10, 10, 2)));
+        }
+        cpu.add_inst(Instruction::Add(Operand::new(11, 10, 2)));
+        cpu.add_inst(Instruction::Ld(12, 10, 0, Width::Word));
+        let mut winners = vec![];
+        while !cpu.done() {
+            let events = cpu.step();
+            if events.cycle >= 13 {
+                winners.extend(events.written_back.iter().map(|cdb| cdb.pc));
+            }
+        }
+        winners
+    }
+
+    #[test]
+    fn cdb_arbitration_policies() {
+        assert_eq!("unit".parse::<CdbArbitration>(), Ok(CdbArbitration::UnitOrder));
+        assert!("youngest".parse::<CdbArbitration>().is_err());
+        // 乘法（序号 0）最老，加法（序号 6）所在的执行单元编号最小
+        let mut trace = Trace::new("traces/test_cdb_oldest.txt");
+        assert_eq!(cdb_winners(CdbArbitration::OldestFirst, &mut trace), vec![0, 6, 7]);
+        let mut trace = Trace::new("traces/test_cdb_unit.txt");
+        assert_eq!(cdb_winners(CdbArbitration::UnitOrder, &mut trace), vec![6, 0, 7]);
+        // 轮流仲裁每周期前进一个执行单元，第 13 个周期从单元 2 开始，第 14 个周期从单元 3 开始
+        let mut trace = Trace::new("traces/test_cdb_round_robin.txt");
+        assert_eq!(cdb_winners(CdbArbitration::RoundRobin, &mut trace), vec![0, 7, 6]);
+    }
+
+    #[test]
+    fn textbook_stage_latency() {
+        let mut trace = Trace::new("traces/test_stage_latency.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_stage_latency(StageLatency::textbook());
+        cpu.add_inst(Instruction::Add(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Add(Operand::new(4, 1, 1)));
+        // 记录每条指令各阶段发生的周期
+        let (mut issued, mut dispatched, mut written, mut committed) = (vec![], vec![], vec![], vec![]);
+        while !cpu.done() {
+            let events = cpu.step();
+            issued.extend(events.issued.iter().map(|_| events.cycle));
+            dispatched.extend(events.dispatched.iter().map(|_| events.cycle));
+            written.extend(events.written_back.iter().map(|_| events.cycle));
+            committed.extend(events.committed.iter().map(|_| events.cycle));
+        }
+        assert_eq!(issued, vec![1, 1]);
+        assert_eq!(dispatched, vec![2, 5]);
+        assert_eq!(written, vec![4, 7]);
+        assert_eq!(committed, vec![5, 8]);
+    }
+
+    #[test]
+    fn run_for_and_run_until_report_cycle_events() {
+        let mut trace = Trace::new("traces/test_run_until.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_regs(2, 3);
+        cpu.set_regs(3, 4);
+        cpu.add_inst(Instruction::Add(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Mul(Operand::new(4, 1, 1)));
+        cpu.add_inst(Instruction::Sub(Operand::new(5, 2, 3)));
+        let first = cpu.step();
+        assert_eq!(first.cycle, 1);
+        assert_eq!(first.issued.iter().map(|(pc, _)| *pc).collect::<Vec<_>>(), vec![0, 1, 2]);
+        // 加法在第 3 个周期提交
+        assert_eq!(cpu.run_until(|cpu| cpu.regs()[1] != 0), 2);
+        assert_eq!(cpu.cycles(), 3);
+        assert_eq!(cpu.regs()[1], 7);
+        // 减法已经写回，但要等乘法按序提交
+        assert_eq!(cpu.run_for(5), 5);
+        assert_eq!(cpu.cycles(), 8);
+        assert_eq!(cpu.regs()[5], 0);
+        // 逐周期记录有事件发生的 (周期, 发射, 写回, 提交) 的指令序号
+        let mut events = vec![];
+        while !cpu.done() {
+            let cycle = cpu.step();
+            let issued: Vec<usize> = cycle.issued.iter().map(|(pc, _)| *pc).collect();
+            let written_back: Vec<usize> = cycle.written_back.iter().map(|cdb| cdb.pc).collect();
+            let committed: Vec<usize> = cycle.committed.iter().map(|retired| retired.pc).collect();
+            if !issued.is_empty() || !written_back.is_empty() || !committed.is_empty() {
+                events.push((cycle.cycle, issued, written_back, committed));
+            }
+        }
+        assert_eq!(events, vec![(15, vec![], vec![1], vec![1, 2])]);
+        assert_eq!(cpu.cycles(), 16);
+        assert_eq!(cpu.run_for(10), 0);
+        assert_eq!(cpu.regs()[4], 49);
+        assert_eq!(cpu.regs()[5], -1);
+    }
+
+    #[test]
+    fn cycle_limit_stops_long_run() {
+        let mut trace = Trace::new("traces/test_cycle_limit.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_cycle_limit(Some(20));
+        cpu.set_regs(2, 1);
+        // 一串相互依赖的除法需要远多于 20 个周期
+        for _ in 0..10 {
+            cpu.add_inst(Instruction::Div(Operand::new(1, 1, 2)));
+        }
+        let stats = cpu.run();
+        let halt = cpu.halted().unwrap();
+        assert_eq!(halt.reason, HaltReason::CycleLimit(20));
+        assert_eq!(halt.cycle, 20);
+        assert_eq!(stats.cycles, 20);
+        assert_eq!(stats.committed, 0);
+        assert!(halt.oldest.starts_with("pc 0 DIV,R1,R1,R2 executing from RS#"), "{}", halt.oldest);
+        assert_eq!(stats.halted, Some(format!("cycle limit 20 reached: {}", halt.oldest)));
+    }
+
+    #[test]
+    fn watchdog_reports_deadlock() {
+        // 有加减法保留站却没有加减法执行单元，加法永远无法执行
+        let mut trace = Trace::new("traces/test_watchdog.txt");
+        let mut cpu = TomasuloCpu::new_empty(&mut trace);
+        cpu.add_rs(ResStationType::AddSub, 2);
+        cpu.add_rob(4);
+        cpu.set_watchdog(5);
+        cpu.add_inst(Instruction::Add(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Add(Operand::new(4, 1, 1)));
+        let stats = cpu.run();
+        let halt = cpu.halted().unwrap();
+        assert_eq!(halt.reason, HaltReason::Deadlock(5));
+        // 发射不算进展，连续 5 个周期既没有广播也没有提交
+        assert_eq!(halt.cycle, 5);
+        assert_eq!(halt.oldest, "pc 0 ADD,R1,R2,R3 RS#0 waiting for a free AddSub exec unit");
+        assert!(halt.dump.contains("Exec units:"));
+        assert_eq!(stats.halted, Some(format!("no progress for 5 cycles: {}", halt.oldest)));
+        assert_eq!(stats.committed, 0);
+    }
+
+    #[test]
+    fn invalid_instruction_halts_after_older_commit() {
+        let mut trace = Trace::new("traces/test_invalid_instruction.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_regs(2, 3);
+        cpu.add_inst(Instruction::Add(Operand::new(1, 2, 2)));
+        cpu.add_inst(Instruction::Invalid);
+        cpu.add_inst(Instruction::Add(Operand::new(3, 2, 2)));
+        let stats = cpu.run();
+        let halt = cpu.halted().unwrap();
+        assert_eq!(halt.reason, HaltReason::InvalidInstruction(1));
+        assert_eq!(halt.oldest, "pc 1 INVALID waiting to issue");
+        // 更早的加法已经提交，之后的指令没有发射
+        assert_eq!(stats.committed, 1);
+        assert_eq!(cpu.regs()[1], 6);
+        assert_eq!(cpu.regs()[3], 0);
+    }
+
+    #[test]
+    fn front_end_redirects_on_jump() {
+        let mut trace = Trace::new("traces/test_front_end.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_front_end(Some(FrontEnd{ fetch_width: 1, queue_depth: 4, decode_delay: 1, redirect_penalty: 2 }));
+        cpu.add_inst(Instruction::Add(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Jump(2, 3));
+        cpu.add_inst(Instruction::Add(Operand::new(4, 2, 3)));
+        cpu.add_inst(Instruction::Add(Operand::new(5, 2, 3)));
+        let (mut fetched, mut issued) = (vec![], vec![]);
+        while !cpu.done() {
+            let events = cpu.step();
+            fetched.extend(events.fetched.iter().map(|_| events.cycle));
+            issued.extend(events.issued.iter().map(|_| events.cycle));
+        }
+        // 跳转之后的两个周期无法取指，译码又需要一个周期
+        assert_eq!(fetched, vec![1, 2, 5, 6]);
+        assert_eq!(issued, vec![2, 3, 6, 7]);
+        let stats = cpu.stats();
+        assert_eq!(stats.redirects, 1);
+        assert_eq!(stats.stalls.fetch_redirect, 2);
+        assert!(stats.slots.branch_blocked > 0);
+        assert!(stats.slots.fetch_starved > 0);
+        assert_eq!(stats.committed, 4);
+    }
+
+    #[test]
+    fn unit_contention_matches_single_cycle() {
+        let mut single_trace = Trace::new("traces/test_single_reference.txt");
+        let mut single = SingleCycleCpu::new(&mut single_trace);
+        let mut trace = Trace::new("traces/test_contention_reference.txt");
+        let mut cpu = contended_cpu(&mut trace);
+        for (addr, val) in [(0x0, 7), (0x4, 5)] {
+            single.write_memory(addr, val);
+            cpu.write_memory(addr, val);
+        }
+        for inst in mixed_program() {
+            single.add_inst(inst);
+            cpu.add_inst(inst);
+        }
+        single.run();
+        let stats = cpu.run();
+        assert!(stats.halted.is_none(), "{:?}", stats.halted);
+        assert_eq!(stats.committed, mixed_program().len());
+        assert_eq!(cpu.regs(), single.regs());
+    }
+
+    #[test]
+    fn physical_register_file_matches_rob() {
+        let mut rob_trace = Trace::new("traces/test_rename_rob.txt");
+        let mut rob_cpu = contended_cpu(&mut rob_trace);
+        let mut trace = Trace::new("traces/test_rename_prf.txt");
+        let mut cpu = contended_cpu(&mut trace);
+        cpu.set_rename_mode(RenameMode::PhysRegFile(40));
+        for reg in 0..32 {
+            rob_cpu.set_regs(reg, reg as i32 + 1);
+            cpu.set_regs(reg, reg as i32 + 1);
+        }
+        for (addr, val) in [(0x0, 7), (0x4, 5)] {
+            rob_cpu.write_memory(addr, val);
+            cpu.write_memory(addr, val);
+        }
+        for inst in mixed_program() {
+            rob_cpu.add_inst(inst);
+            cpu.add_inst(inst);
+        }
+        let rob_stats = rob_cpu.run();
+        let stats = cpu.run();
+        assert!(stats.halted.is_none(), "{:?}", stats.halted);
+        assert_eq!(cpu.regs(), rob_cpu.regs());
+        // 物理寄存器足够时两种重命名方式的时序相同
+        assert_eq!(stats.cycles, rob_stats.cycles);
+        let prf = cpu.physical_registers().unwrap();
+        assert_eq!(prf.free_count(), 8);
+        for reg in 0..32 {
+            assert_eq!(prf.value(prf.retired_mapping(reg)), cpu.regs()[reg]);
+        }
+    }
+
+    #[test]
+    fn physical_register_file_stalls_on_free_list() {
+        let mut trace = Trace::new("traces/test_free_list.txt");
+        let mut cpu = contended_cpu(&mut trace);
+        // 只有一个空闲的物理寄存器，每次只能有一条写寄存器的指令在执行
+        cpu.set_rename_mode(RenameMode::PhysRegFile(33));
+        cpu.set_regs(2, 3);
+        cpu.set_regs(3, 4);
+        for target in [1, 4, 5] {
+            cpu.add_inst(Instruction::Add(Operand::new(target, 2, 3)));
+        }
+        let stats = cpu.run();
+        assert!(stats.halted.is_none(), "{:?}", stats.halted);
+        assert!(stats.stalls.free_list_empty > 0);
+        assert_eq!(cpu.regs()[1], 7);
+        assert_eq!(cpu.regs()[4], 7);
+        assert_eq!(cpu.regs()[5], 7);
+    }
+
+    /// 每周期提交（经典模式下为写回完成）的指令序号
+    fn retire_order(mode: RenameMode, trace: &mut Trace) -> Vec<usize> {
+        let mut cpu = TomasuloCpu::new(trace);
+        cpu.set_rename_mode(mode);
+        cpu.set_regs(2, 12);
+        cpu.set_regs(3, 4);
+        // 除法占用乘除法单元 24 个周期
+        cpu.add_inst(Instruction::Div(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Add(Operand::new(4, 2, 3)));
+        cpu.add_inst(Instruction::Sub(Operand::new(5, 1, 3)));
+        let mut retired = vec![];
+        while !cpu.done() {
+            retired.extend(cpu.step().committed.into_iter().map(|item| item.pc));
+        }
+        assert_eq!(&cpu.regs()[1..6], &[3, 12, 4, 16, -1]);
+        retired
+    }
+
+    #[test]
+    fn classic_tomasulo_completes_out_of_order() {
+        let mut trace = Trace::new("traces/test_retire_rob.txt");
+        assert_eq!(retire_order(RenameMode::Rob, &mut trace), vec![0, 1, 2]);
+        // 没有 ROB 时加法先于除法写回寄存器，不能提供精确异常
+        let mut trace = Trace::new("traces/test_retire_classic.txt");
+        assert_eq!(retire_order(RenameMode::Classic, &mut trace), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn classic_tomasulo_matches_single_cycle() {
+        let mut single_trace = Trace::new("traces/test_classic_reference.txt");
+        let mut single = SingleCycleCpu::new(&mut single_trace);
+        let mut trace = Trace::new("traces/test_classic.txt");
+        let mut cpu = TomasuloCpu::new_empty(&mut trace);
+        // 经典模式不需要 ROB
+        cpu.add_rs(ResStationType::AddSub, 2);
+        cpu.add_rs(ResStationType::MulDiv, 2);
+        cpu.add_rs(ResStationType::LoadStore, 2);
+        cpu.add_rs(ResStationType::JUMP, 1);
+        cpu.add_exec_unit(ResStationType::AddSub, 1);
+        cpu.add_exec_unit(ResStationType::MulDiv, 1);
+        cpu.add_exec_unit(ResStationType::LoadStore, 1);
+        cpu.add_exec_unit(ResStationType::JUMP, 1);
+        cpu.set_rename_mode(RenameMode::Classic);
+        for (addr, val) in [(0x0, 7), (0x4, 5)] {
+            single.write_memory(addr, val);
+            cpu.write_memory(addr, val);
+        }
+        for inst in mixed_program() {
+            single.add_inst(inst);
+            cpu.add_inst(inst);
+        }
+        single.run();
+        let stats = cpu.run();
+        assert!(stats.halted.is_none(), "{:?}", stats.halted);
+        assert_eq!(stats.committed, mixed_program().len());
+        assert_eq!(cpu.regs(), single.regs());
+    }
+
+    /// 经典模式下两个 LoadStore 单元，访存指令之间只能通过内存地址产生依赖
+    fn classic_memory_order<'a>(program: &[Instruction], trace: &'a mut Trace) -> (TomasuloCpu<'a>, crate::cpu::Stats) {
+        let mut cpu = TomasuloCpu::new_empty(trace);
+        cpu.add_rs(ResStationType::MulDiv, 1);
+        cpu.add_rs(ResStationType::LoadStore, 3);
+        cpu.add_exec_unit(ResStationType::MulDiv, 1);
+        cpu.add_exec_unit(ResStationType::LoadStore, 2);
+        cpu.set_rename_mode(RenameMode::Classic);
+        cpu.write_memory(0x0, 7);
+        cpu.set_regs(6, 99);
+        cpu.set_regs(7, 42);
+        for inst in program {
+            cpu.add_inst(*inst);
+        }
+        let stats = cpu.run();
+        (cpu, stats)
+    }
+
+    #[test]
+    fn classic_tomasulo_keeps_memory_order() {
+        // 乘法推迟了 load 的地址，之后写同一地址的 store 不能先于 load 执行
+        let mut trace = Trace::new("traces/test_classic_load_store.txt");
+        let (cpu, stats) = classic_memory_order(&[
+            Instruction::Mul(Operand::new(4, 0, 2)),
+            Instruction::Ld(5, 4, 0x0, Width::Word),
+            Instruction::Sd(6, 0, 0x0, StoreWidth::Word),
+        ], &mut trace);
+        assert!(stats.halted.is_none(), "{:?}", stats.halted);
+        assert_eq!(cpu.regs()[5], 7);
+        assert_eq!(cpu.read_memory(0x0), 99);
+
+        // 同一地址的两个 store 按程序顺序写入内存
+        let mut trace = Trace::new("traces/test_classic_store_store.txt");
+        let (cpu, stats) = classic_memory_order(&[
+            Instruction::Mul(Operand::new(4, 0, 2)),
+            Instruction::Sd(7, 4, 0x0, StoreWidth::Word),
+            Instruction::Sd(6, 0, 0x0, StoreWidth::Word),
+        ], &mut trace);
+        assert!(stats.halted.is_none(), "{:?}", stats.halted);
+        assert_eq!(cpu.read_memory(0x0), 99);
+    }
+
+    #[test]
+    fn classic_stores_use_cache_write_policy() {
+        // 经典模式下 store 同样经过 store buffer 与 cache，写回 cache 中的脏块在替换时才写回下一级
+        let mut trace = Trace::new("traces/test_classic_write_back.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_rename_mode(RenameMode::Classic);
+        // 直接映射，0x0 与 0x100 冲突
+        cpu.set_cache(Some(CacheHierarchy::new("256,1,16,lru,1".parse().unwrap(), None, 10).unwrap()));
+        cpu.set_regs(1, 5);
+        cpu.add_inst(Instruction::Sd(1, 0, 0x0, StoreWidth::Word));
+        cpu.add_inst(Instruction::Sd(1, 0, 0x100, StoreWidth::Word));
+        let stats = cpu.run();
+        assert!(stats.halted.is_none(), "{:?}", stats.halted);
+        assert_eq!(cpu.read_memory(0x0), 5);
+        assert_eq!(cpu.read_memory(0x100), 5);
+        assert!(cpu.store_buffer().is_empty());
+        let l1 = &cpu.cache().unwrap().stats()[0];
+        assert_eq!(l1.misses, 2);
+        assert_eq!(l1.writebacks, 1);
+    }
+
+    #[test]
+    fn scoreboard_matches_single_cycle() {
+        let mut single_trace = Trace::new("traces/test_scoreboard_reference.txt");
+        let mut single = SingleCycleCpu::new(&mut single_trace);
+        let mut trace = Trace::new("traces/test_scoreboard.txt");
+        let mut cpu = ScoreboardCpu::new(&mut trace);
+        for (addr, val) in [(0x0, 7), (0x4, 5)] {
+            single.write_memory(addr, val);
+            cpu.write_memory(addr, val);
+        }
+        let mut program = mixed_program();
+        // store 之后的 load 必须读到新写入的值
+        program.push(Instruction::Sd(1, 0, 0x8, StoreWidth::Word));
+        program.push(Instruction::Ld(10, 0, 0x8, Width::Word));
+        for inst in program.iter() {
+            single.add_inst(*inst);
+            cpu.add_inst(*inst);
+        }
+        single.run();
+        let stats = cpu.run();
+        assert_eq!(stats.committed, program.len());
+        assert_eq!(cpu.regs(), single.regs());
+        assert_eq!(cpu.read_memory(0x8), single.read_memory(0x8));
+    }
+
+    #[test]
+    fn scoreboard_stalls_on_war_and_waw() {
+        let mut trace = Trace::new("traces/test_scoreboard_hazard.txt");
+        let mut cpu = ScoreboardCpu::new(&mut trace);
+        cpu.set_regs(2, 12);
+        cpu.set_regs(3, 4);
+        cpu.set_regs(5, 1);
+        cpu.add_inst(Instruction::Div(Operand::new(1, 2, 3)));
+        // 等待除法结果才能读取 R5
+        cpu.add_inst(Instruction::Add(Operand::new(4, 1, 5)));
+        // 写 R5 必须等前一条加法读取 R5 之后
+        cpu.add_inst(Instruction::Add(Operand::new(5, 2, 3)));
+        // 与除法写同一个寄存器，只能等除法写结果后发射
+        cpu.add_inst(Instruction::Sub(Operand::new(1, 2, 3)));
+        let stats = cpu.run();
+        assert!(stats.stalls.war_hazard > 0);
+        assert!(stats.slots.waw_hazard > 0);
+        assert_eq!(&cpu.regs()[1..6], &[8, 12, 4, 4, 16]);
+    }
+
+    #[test]
+    fn structural_hazards_count_as_unit_busy() {
+        // 计分板有两个乘除法单元，第三条除法要等其中一个空闲
+        let mut trace = Trace::new("traces/test_scoreboard_unit_busy.txt");
+        let mut cpu = ScoreboardCpu::new(&mut trace);
+        cpu.set_regs(2, 12);
+        cpu.set_regs(3, 4);
+        for target in 1..=3 {
+            cpu.add_inst(Instruction::Div(Operand::new(target, 2, 3)));
+        }
+        let stats = cpu.run();
+        assert!(stats.stalls.unit_busy["MulDiv"] > 0);
+        assert!(stats.stalls.rs_full.is_empty());
+        // 流水线只有一个乘除法单元
+        let mut trace = Trace::new("traces/test_pipeline_unit_busy.txt");
+        let mut cpu = PipelinedCpu::new(&mut trace);
+        cpu.set_regs(2, 12);
+        cpu.set_regs(3, 4);
+        cpu.add_inst(Instruction::Div(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Div(Operand::new(4, 2, 3)));
+        let stats = cpu.run();
+        assert_eq!(stats.stalls.unit_busy["MulDiv"], crate::cpu::tomasulo::DIV_CYCLES - 1);
+        assert!(stats.stalls.rs_full.is_empty());
+        assert_eq!(&cpu.regs()[1..5], &[3, 12, 4, 3]);
+    }
+
+    /// 每条指令进入 EX 的周期
+    fn pipeline_issue_cycles(forwarding: Forwarding, trace: &mut Trace) -> Vec<usize> {
+        let mut cpu = PipelinedCpu::new(trace);
+        cpu.set_forwarding(forwarding);
+        cpu.add_inst(Instruction::Add(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Add(Operand::new(4, 1, 1)));
+        cpu.add_inst(Instruction::Ld(5, 4, 0, Width::Word));
+        cpu.add_inst(Instruction::Add(Operand::new(6, 5, 5)));
+        let mut issued = vec![];
+        while !cpu.done() {
+            let events = cpu.step();
+            issued.extend(events.issued.iter().map(|_| events.cycle));
+        }
+        issued
+    }
+
+    #[test]
+    fn pipeline_forwarding_paths() {
+        let mut trace = Trace::new("traces/test_pipeline_full.txt");
+        // 第二条加法等待第一条加法让出加减法单元，结果通过 EX/MEM 旁路获得；
+        // load 的结果要到 MEM 之后才能通过 MEM/WB 旁路获得
+        assert_eq!(pipeline_issue_cycles(Forwarding::default(), &mut trace), vec![3, 5, 7, 10]);
+        let mut trace = Trace::new("traces/test_pipeline_none.txt");
+        // 没有旁路时要等写回的下一周期才能译码读取寄存器
+        assert_eq!(pipeline_issue_cycles(Forwarding::none(), &mut trace), vec![3, 8, 13, 18]);
+    }
+
+    #[test]
+    fn pipeline_overlaps_functional_units() {
+        let mut trace = Trace::new("traces/test_pipeline_units.txt");
+        let mut cpu = PipelinedCpu::new(&mut trace);
+        cpu.set_regs(2, 12);
+        cpu.set_regs(3, 4);
+        // 除法占用乘除法单元 24 个周期
+        cpu.add_inst(Instruction::Div(Operand::new(1, 2, 3)));
+        cpu.add_inst(Instruction::Add(Operand::new(4, 2, 3)));
+        cpu.add_inst(Instruction::Add(Operand::new(5, 4, 4)));
+        cpu.add_inst(Instruction::Add(Operand::new(1, 2, 2)));
+        let (mut issued, mut committed) = (vec![], vec![]);
+        while !cpu.done() {
+            let events = cpu.step();
+            issued.extend(events.issued.iter().map(|(pc, _)| (*pc, events.cycle)));
+            committed.extend(events.committed.iter().map(|retired| retired.pc));
+        }
+        // 独立的加法在除法执行期间发射并先完成，与除法写同一寄存器的加法要等除法离开 EX
+        assert_eq!(issued, vec![(0, 3), (1, 4), (2, 6), (3, 27)]);
+        assert_eq!(committed, vec![1, 2, 0, 3]);
+        assert!(cpu.stats().slots.waw_hazard > 0);
+        assert_eq!(&cpu.regs()[1..6], &[24, 12, 4, 16, 32]);
+    }
+
+    #[test]
+    fn pipeline_matches_single_cycle() {
+        let mut single_trace = Trace::new("traces/test_pipeline_reference.txt");
+        let mut single = SingleCycleCpu::new(&mut single_trace);
+        let mut trace = Trace::new("traces/test_pipeline.txt");
+        let mut cpu = PipelinedCpu::new(&mut trace);
+        for (addr, val) in [(0x0, 7), (0x4, 5)] {
+            single.write_memory(addr, val);
+            cpu.write_memory(addr, val);
+        }
+        let mut program = mixed_program();
+        program.push(Instruction::Sd(1, 0, 0x8, StoreWidth::Word));
+        program.push(Instruction::Ld(10, 0, 0x8, Width::Word));
+        for inst in program.iter() {
+            single.add_inst(*inst);
+            cpu.add_inst(*inst);
+        }
+        single.run();
+        let stats = cpu.run();
+        assert_eq!(stats.committed, program.len());
+        assert_eq!(cpu.regs(), single.regs());
+        assert!(stats.slots.data_hazard > 0);
+    }
+
+    #[test]
+    fn cache_replacement_policies() {
+        // 只有一组的两路 cache，访问 A B A C A
+        let hits = |replacement: Replacement| {
+            let mut cache = Cache::new("L1", CacheConfig{ size: 32, assoc: 2, line_size: 16, replacement, hit_latency: 1 }).unwrap();
+            [0x0, 0x10, 0x4, 0x20, 0x8].into_iter().filter(|addr| cache.access(*addr, false).hit()).count()
+        };
+        assert_eq!(hits(Replacement::Lru), 2);
+        assert_eq!(hits(Replacement::Fifo), 1);
+        assert!("64,4,16,lru,1".parse::<CacheConfig>().is_ok());
+        assert!("32,4,16,lru,1".parse::<CacheConfig>().is_err());
+        assert!("64,0,16,lru,1".parse::<CacheConfig>().is_err());
+        assert!("64,4,0,lru,1".parse::<CacheConfig>().is_err());
+        let invalid = CacheConfig{ size: 64, assoc: 0, line_size: 16, replacement: Replacement::Lru, hit_latency: 1 };
+        assert_eq!(invalid.sets(), 0);
+        assert!(Cache::new("L1", invalid).is_err());
+        let l1 = "64,4,16,lru,1".parse().unwrap();
+        assert!(CacheHierarchy::new(l1, Some(invalid), 10).is_err());
+        assert!(CacheHierarchy::new(l1, None, 10).unwrap().set_mshrs(Some(0)).is_err());
+    }
+
+    #[test]
+    fn cache_makes_load_latency_variable() {
+        let mut trace = Trace::new("traces/test_cache.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        // 直接映射，两组，0x0 与 0x20 冲突
+        let l1 = "32,1,16,lru,1".parse().unwrap();
+        cpu.set_cache(Some(CacheHierarchy::new(l1, None, 10).unwrap()));
+        for (addr, val) in [(0x0, 7), (0x4, 5), (0x20, 3)] {
+            cpu.write_memory(addr, val);
+        }
+        for (reg, addr) in [(1, 0x0), (2, 0x4), (3, 0x20), (4, 0x0)] {
+            cpu.add_inst(Instruction::Ld(reg, 0, addr, Width::Word));
+        }
+        let stats = cpu.run();
+        assert_eq!(&cpu.regs()[1..5], &[7, 5, 3, 7]);
+        assert_eq!(stats.cache.len(), 1);
+        assert_eq!((stats.cache[0].hits, stats.cache[0].misses), (1, 3));
+        assert_eq!(stats.avg_memory_latency, Some((11.0 * 3.0 + 1.0) / 4.0));
+    }
+
+    /// 不同 MSHR 个数下的统计信息
+    fn mshr_stats(count: usize, trace: &mut Trace) -> (TomasuloCpu<'_>, crate::cpu::Stats) {
+        let mut cpu = TomasuloCpu::new(trace);
+        let mut cache = CacheHierarchy::new("64,1,16,lru,1".parse().unwrap(), None, 10).unwrap();
+        cache.set_mshrs(Some(count)).unwrap();
+        cpu.set_cache(Some(cache));
+        for (addr, val) in [(0x0, 7), (0x4, 5), (0x10, 3), (0x20, 1)] {
+            cpu.write_memory(addr, val);
+        }
+        for (reg, addr) in [(1, 0x0), (2, 0x4), (3, 0x10), (4, 0x20)] {
+            cpu.add_inst(Instruction::Ld(reg, 0, addr, Width::Word));
+        }
+        let stats = cpu.run();
+        assert_eq!(&cpu.regs()[1..5], &[7, 5, 3, 1]);
+        (cpu, stats)
+    }
+
+    #[test]
+    fn mshrs_overlap_and_merge_misses() {
+        let mut trace = Trace::new("traces/test_mshr_1.txt");
+        let (_, blocking) = mshr_stats(1, &mut trace);
+        let mut trace = Trace::new("traces/test_mshr_4.txt");
+        let (_, overlapped) = mshr_stats(4, &mut trace);
+        for stats in [&blocking, &overlapped] {
+            let mshr = stats.mshr.as_ref().unwrap();
+            assert_eq!((mshr.primary, mshr.merged), (3, 1));
+        }
+        assert!(blocking.stalls.mshr_full > 0);
+        assert_eq!(blocking.mshr.as_ref().unwrap().peak, 1);
+        assert_eq!(overlapped.stalls.mshr_full, 0);
+        assert!(overlapped.mshr.as_ref().unwrap().mlp > 1.0);
+        assert!(overlapped.cycles + 10 < blocking.cycles, "{} {}", overlapped.cycles, blocking.cycles);
+    }
+
+    /// 先写后读同一地址，load 需要得到 store 的数据
+    fn store_program() -> Vec<Instruction> {
+        vec![
+            Instruction::Ld(1, 0, 0x0, Width::Word),
+            Instruction::Mul(Operand::new(2, 1, 1)),
+            Instruction::Sd(2, 0, 0x8, StoreWidth::Word),
+            Instruction::Ld(3, 0, 0x8, Width::Word),
+            Instruction::Sd(3, 0, 0x0, StoreWidth::Word),
+            Instruction::Ld(4, 0, 0x0, Width::Word),
+            Instruction::Add(Operand::new(5, 4, 3)),
+        ]
+    }
+
+    #[test]
+    fn stores_forward_and_reach_memory() {
+        let mut single_trace = Trace::new("traces/test_store_reference.txt");
+        let mut single = SingleCycleCpu::new(&mut single_trace);
+        single.write_memory(0x0, 7);
+        for inst in store_program() {
+            single.add_inst(inst);
+        }
+        single.run();
+        for mode in [RenameMode::Rob, RenameMode::PhysRegFile(40), RenameMode::Classic] {
+            let mut trace = Trace::new("traces/test_store.txt");
+            let mut cpu = TomasuloCpu::new(&mut trace);
+            cpu.set_rename_mode(mode);
+            cpu.write_memory(0x0, 7);
+            for inst in store_program() {
+                cpu.add_inst(inst);
+            }
+            let stats = cpu.run();
+            assert!(stats.halted.is_none(), "{:?}", stats.halted);
+            assert_eq!(stats.committed, store_program().len());
+            assert_eq!(cpu.regs(), single.regs(), "{:?}", mode);
+            assert_eq!((cpu.read_memory(0x0), cpu.read_memory(0x8)), (49, 49), "{:?}", mode);
+            assert!(cpu.store_buffer().is_empty());
+        }
+    }
+
+    #[test]
+    fn store_buffer_stalls_commit_when_full() {
+        let mut trace = Trace::new("traces/test_store_buffer.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        let mut cache = CacheHierarchy::new("64,1,16,lru,1".parse().unwrap(), None, 10).unwrap();
+        cache.set_write_policy("wt,nwa".parse().unwrap());
+        cpu.set_cache(Some(cache));
+        cpu.set_store_buffer_size(1);
+        cpu.set_regs(1, 5);
+        for addr in [0x0, 0x10, 0x20] {
+            cpu.add_inst(Instruction::Sd(1, 0, addr, StoreWidth::Word));
+        }
+        let stats = cpu.run();
+        assert!(stats.stalls.store_buffer_full > 0);
+        for addr in [0x0, 0x10, 0x20] {
+            assert_eq!(cpu.read_memory(addr), 5);
+        }
+        // 写不分配时 store 不会调入块
+        assert_eq!(stats.cache[0].hits, 0);
+    }
+
+    #[test]
+    fn write_back_defers_memory_writes() {
+        // 直接映射、只有一组，0x0 与 0x10 冲突
+        let config: CacheConfig = "16,1,16,lru,1".parse().unwrap();
+        let latencies = |policy: WritePolicy| {
+            let mut cache = CacheHierarchy::new(config, None, 10).unwrap();
+            cache.set_write_policy(policy);
+            let latencies: Vec<usize> = [0x0, 0x4, 0x10].into_iter().map(|addr| cache.write(addr, 0).unwrap()).collect();
+            (latencies, cache.stats()[0].writebacks)
+        };
+        // 写回：缺失调入，命中只写 L1，替换脏块时写回
+        assert_eq!(latencies("wb,wa".parse().unwrap()), (vec![11, 1, 21], 1));
+        // 写直达：每次都写主存
+        assert_eq!(latencies("wt,wa".parse().unwrap()), (vec![21, 11, 21], 0));
+        assert_eq!(latencies("wt,nwa".parse().unwrap()), (vec![11, 11, 11], 0));
+        // 写直达的写命中虽然要写主存，但不是缺失，不占用 MSHR
+        let mut cache = CacheHierarchy::new(config, None, 10).unwrap();
+        cache.set_write_policy("wt,wa".parse().unwrap());
+        assert_eq!(cache.read(0x0, 0), Some(11));
+        cache.set_mshrs(Some(1)).unwrap();
+        assert_eq!(cache.write(0x4, 20), Some(11));
+        assert!(cache.mshrs().is_empty());
+        assert_eq!(cache.mshr_stats().unwrap().primary, 0);
+    }
+
+    /// 按字节与半字读写同一个字
+    fn sized_program() -> Vec<Instruction> {
+        let parser = crate::parser::Parser::new();
+        ["SB,R1,R0,0", "SB,R2,R0,1", "SH,R3,R0,2", "LW,R4,R0,0", "LB,R5,R0,1", "LBU,R6,R0,1", "LH,R7,R0,2", "LHU,R8,R0,2"]
+            .into_iter()
+            .map(|inst| parser.parse(inst))
+            .collect()
+    }
+
+    #[test]
+    fn sized_loads_and_stores() {
+        for (endian, word) in [(Endian::Little, 0x8001_ff12u32 as i32), (Endian::Big, 0x12ff_8001)] {
+            let mut single_trace = Trace::new("traces/test_sized_reference.txt");
+            let mut single = SingleCycleCpu::new(&mut single_trace);
+            let mut trace = Trace::new("traces/test_sized.txt");
+            let mut cpu = TomasuloCpu::new(&mut trace);
+            single.set_endian(endian);
+            cpu.set_endian(endian);
+            for (reg, val) in [(1, 0x12), (2, -1), (3, 0x8001)] {
+                single.set_regs(reg, val);
+                cpu.set_regs(reg, val);
+            }
+            for inst in sized_program() {
+                single.add_inst(inst);
+                cpu.add_inst(inst);
+            }
+            single.run();
+            cpu.run();
+            assert_eq!(&single.regs()[4..9], &[word, -1, 0xff, -0x7fff, 0x8001], "{:?}", endian);
+            assert_eq!(cpu.regs(), single.regs(), "{:?}", endian);
+            assert_eq!(cpu.read_memory(0x0), word);
+        }
+    }
+
+    #[test]
+    fn misaligned_access_policies() {
+        let program = [
+            Instruction::Add(Operand::new(1, 2, 2)),
+            Instruction::Ld(3, 0, 0x2, Width::Word),
+            Instruction::Add(Operand::new(4, 2, 2)),
+        ];
+        let run_single = |misaligned: Misaligned| {
+            let mut trace = Trace::new("traces/test_misaligned_single.txt");
+            let mut cpu = SingleCycleCpu::new(&mut trace);
+            cpu.set_misaligned(misaligned);
+            cpu.set_regs(2, 1);
+            for inst in program {
+                cpu.add_inst(inst);
+            }
+            let stats = cpu.run();
+            (stats.cycles, stats.halted, cpu.regs()[1], cpu.regs()[4])
+        };
+        let (allow, halted, _, _) = run_single(Misaligned::Allow);
+        assert!(halted.is_none());
+        let (split, _, _, _) = run_single(Misaligned::Split);
+        assert!(split > allow);
+        let (_, halted, r1, r4) = run_single(Misaligned::Trap);
+        assert!(halted.is_some_and(|halted| halted.starts_with("pc 1: misaligned")));
+        assert_eq!((r1, r4), (2, 0));
+
+        // 精确异常：更早的指令已提交，之后的指令都不提交
+        let mut trace = Trace::new("traces/test_misaligned.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_misaligned(Misaligned::Trap);
+        cpu.set_regs(2, 1);
+        for inst in program {
+            cpu.add_inst(inst);
+        }
+        let stats = cpu.run();
+        assert_eq!(stats.committed, 1);
+        assert!(stats.halted.is_some_and(|halted| halted.starts_with("pc 1: misaligned")));
+        assert_eq!((cpu.regs()[1], cpu.regs()[4]), (2, 0));
+    }
+
+    #[test]
+    fn split_access_touches_cache_all_or_nothing() {
+        // 0xe 与 0x11 分别位于块 0 与块 1
+        let parts = [0xe, 0x11];
+        let mut cache = CacheHierarchy::new("64,1,16,lru,1".parse().unwrap(), None, 10).unwrap();
+        cache.set_mshrs(Some(2)).unwrap();
+        assert_eq!(cache.read(0x40, 0), Some(11));
+        // 只剩一个空闲的 MSHR，两个部分都不访问
+        assert_eq!(cache.access_parts(&parts, 1, false), None);
+        assert_eq!(cache.stats()[0].accesses, 1);
+        assert_eq!(cache.mshrs().len(), 1);
+        assert_eq!(cache.mshr_stats().unwrap().full, 1);
+        assert_eq!(cache.access_parts(&parts, 11, false), Some(22));
+        assert_eq!(cache.mshrs().len(), 2);
+        // 只有一个 MSHR 时由它依次调入两个块
+        let mut cache = CacheHierarchy::new("64,1,16,lru,1".parse().unwrap(), None, 10).unwrap();
+        cache.set_mshrs(Some(1)).unwrap();
+        assert_eq!(cache.access_parts(&parts, 0, false), Some(22));
+        assert_eq!(cache.mshrs().iter().map(|mshr| mshr.ready_at).collect::<Vec<_>>(), vec![22]);
+
+        // 访存异常的 load 不访问 cache
+        let mut trace = Trace::new("traces/test_misaligned_cache.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_misaligned(Misaligned::Trap);
+        cpu.set_cache(Some(CacheHierarchy::new("64,1,16,lru,1".parse().unwrap(), None, 10).unwrap()));
+        cpu.add_inst(Instruction::Ld(3, 0, 0x2, Width::Word));
+        let stats = cpu.run();
+        assert!(stats.halted.is_some());
+        assert_eq!(stats.cache[0].accesses, 0);
+    }
+
+    #[test]
+    fn memory_map_faults() {
+        let map = MemoryMap::standard();
+        assert_eq!(map.check(0x0020_0000, 4, false).unwrap_err(), MemFault::Unmapped(0x0020_0000));
+        assert_eq!(map.check(0x0040_0000, 4, true).unwrap_err(), MemFault::Protection(0x0040_0000, true));
+        assert_eq!(map.check(0x0040_0000, 4, false).unwrap().name, "text");
+        // 跨越区域末尾的访问同样不在映射中
+        assert!(map.find(0x000f_fffe, 4).is_none());
+        assert!("a:0:0x100:rw,b:0x80:0x100:r".parse::<MemoryMap>().is_err());
+
+        let run = |program: &[Instruction]| {
+            let mut trace = Trace::new("traces/test_memory_map.txt");
+            let mut cpu = TomasuloCpu::new(&mut trace);
+            cpu.set_memory_map(Some(MemoryMap::standard()));
+            cpu.set_regs(2, 0x0040_0000);
+            cpu.set_regs(5, 7);
+            for inst in program {
+                cpu.add_inst(*inst);
+            }
+            let stats = cpu.run();
+            (stats, cpu.regs()[1], cpu.regs()[4])
+        };
+        // 读未映射的地址，异常在提交时精确产生
+        let (stats, r1, r4) = run(&[
+            Instruction::Add(Operand::new(1, 5, 5)),
+            Instruction::Ld(3, 2, 0x0010_0000, Width::Word),
+            Instruction::Add(Operand::new(4, 5, 5)),
+        ]);
+        assert_eq!(stats.committed, 1);
+        assert!(stats.halted.is_some_and(|halted| halted.starts_with("pc 1: access to unmapped")));
+        assert_eq!((r1, r4), (14, 0));
+        // 写只读的 text 区域
+        let (stats, _, _) = run(&[
+            Instruction::Ld(3, 2, 0, Width::Word),
+            Instruction::Sd(5, 2, 0, StoreWidth::Word),
+        ]);
+        assert_eq!(stats.committed, 1);
+        assert!(stats.halted.is_some_and(|halted| halted.starts_with("pc 1: write access to protected")));
+
+        let mut trace = Trace::new("traces/test_memory_map_single.txt");
+        let mut cpu = SingleCycleCpu::new(&mut trace);
+        cpu.set_memory_map(Some(MemoryMap::standard()));
+        std::fs::write("traces/test_memory_map_data.txt", "16: 1\n4194304: 2\n").unwrap();
+        Parser::new().read_data(&mut cpu, "traces/test_memory_map_data.txt").unwrap();
+        std::fs::write("traces/test_memory_map_data.txt", "16: 1\n2097152: 2\n").unwrap();
+        assert!(Parser::new().read_data(&mut cpu, "traces/test_memory_map_data.txt").is_err());
+    }
+
+    fn mmio_program() -> Vec<Instruction> {
+        vec![
+            Instruction::Add(Operand::new(1, 5, 5)),
+            Instruction::Sd(1, 0, CONSOLE_BASE + 4, StoreWidth::Word),
+            Instruction::Ld(3, 0, 0x10, Width::Word),
+            Instruction::Sd(3, 0, CONSOLE_BASE, StoreWidth::Byte),
+            Instruction::Ld(6, 0, CYCLE_COUNTER_BASE, Width::Word),
+            Instruction::Sd(5, 0, HALT_BASE, StoreWidth::Word),
+            Instruction::Add(Operand::new(4, 5, 5)),
+            Instruction::Sd(1, 0, CONSOLE_BASE + 4, StoreWidth::Word),
+        ]
+    }
+
+    #[test]
+    fn mmio_devices() {
+        let mut trace = Trace::new("traces/test_mmio_single.txt");
+        let mut single = SingleCycleCpu::new(&mut trace);
+        for (base, device) in standard_devices() {
+            single.attach_device(base, device).unwrap();
+        }
+        single.set_regs(5, 7);
+        single.write_memory(0x10, 'H' as i32);
+        for inst in mmio_program() {
+            single.add_inst(inst);
+        }
+        let stats = single.run();
+        assert_eq!((stats.exit_code, stats.committed), (Some(7), 6));
+        assert_eq!(single.output(), "14\nH");
+        assert!(single.regs()[6] > 0);
+
+        for mode in [RenameMode::Rob, RenameMode::PhysRegFile(40)] {
+            let mut trace = Trace::new("traces/test_mmio.txt");
+            let mut cpu = TomasuloCpu::new(&mut trace);
+            cpu.set_rename_mode(mode);
+            cpu.set_memory_map(Some(MemoryMap::standard()));
+            for (base, device) in standard_devices() {
+                cpu.attach_device(base, device).unwrap();
+            }
+            cpu.set_regs(5, 7);
+            cpu.write_memory(0x10, 'H' as i32);
+            for inst in mmio_program() {
+                cpu.add_inst(inst);
+            }
+            let stats = cpu.run();
+            // 停机寄存器之后的指令可能已经执行，但都不会提交，设备的 store 也不会执行
+            assert!(stats.halted.is_none(), "{:?}", stats.halted);
+            assert_eq!((stats.exit_code, stats.committed), (Some(7), 6), "{:?}", mode);
+            assert_eq!(cpu.output(), "14\nH", "{:?}", mode);
+            assert_eq!(cpu.regs()[4], 0, "{:?}", mode);
+            assert!(cpu.regs()[6] > 0);
+        }
+
+        // 设备必须位于内存映射的某个区域中，且不能重叠
+        let mut trace = Trace::new("traces/test_mmio_attach.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_memory_map(Some(MemoryMap::standard()));
+        assert!(cpu.attach_device(0x0020_0000, Box::new(Console::new())).is_err());
+        cpu.attach_device(CONSOLE_BASE, Box::new(Console::new())).unwrap();
+        assert!(cpu.attach_device(CONSOLE_BASE + 4, Box::new(CycleCounter)).is_err());
+    }
+
+    /// 运行 testcases/ 中的一个程序，返回最终状态
+    fn run_case<C: Cpu>(cpu: &mut C, case: &str) -> ArchState {
+        let parser = Parser::new();
+        parser.read_inst(cpu, format!("testcases/{}_inst.txt", case)).unwrap();
+        parser.read_data(cpu, format!("testcases/{}_data.txt", case)).unwrap();
+        cpu.run();
+        cpu.state()
+    }
+
+    /// 在所有 CPU 上运行 testcases/ 中的程序，并与期望的最终状态比较
+    #[test]
+    fn testcases_match_expected_state() {
+        for case in ["sized"] {
+            let expected = Parser::new().read_state(format!("testcases/{}_expected.txt", case)).unwrap();
+            let mut trace = Trace::new("traces/test_case_single.txt");
+            let mut states = vec![("single".to_string(), run_case(&mut SingleCycleCpu::new(&mut trace), case))];
+            let mut trace = Trace::new("traces/test_case_scoreboard.txt");
+            states.push(("scoreboard".to_string(), run_case(&mut ScoreboardCpu::new(&mut trace), case)));
+            let mut trace = Trace::new("traces/test_case_pipeline.txt");
+            states.push(("pipeline".to_string(), run_case(&mut PipelinedCpu::new(&mut trace), case)));
+            for mode in [RenameMode::Rob, RenameMode::PhysRegFile(40), RenameMode::Classic] {
+                let mut trace = Trace::new("traces/test_case_tomasulo.txt");
+                let mut cpu = TomasuloCpu::new(&mut trace);
+                cpu.set_rename_mode(mode);
+                states.push((format!("{:?}", mode), run_case(&mut cpu, case)));
+            }
+            for (name, state) in states {
+                let diffs = state.diff(&expected);
+                assert!(diffs.is_empty(), "{} {}: {:?}", case, name, diffs);
+            }
+        }
+    }
+
+    #[test]
+    fn state_formats_round_trip() {
+        let mut trace = Trace::new("traces/test_state.txt");
+        let mut cpu = SingleCycleCpu::new(&mut trace);
+        cpu.set_regs(3, -5);
+        cpu.write_memory(0x6, -1);
+        let state = cpu.state();
+        // 非对齐写入的字按所在的对齐字导出
+        assert_eq!(state.memory.keys().copied().collect::<Vec<u32>>(), vec![0x4, 0x8]);
+        assert_eq!(state.to_string().parse::<ArchState>().unwrap(), state);
+        assert_eq!(ArchState::from_json(&state.to_json()).unwrap(), state);
+
+        let mut other = state.clone();
+        other.regs[3] = 1;
+        other.memory.insert(0x20, 9);
+        assert_eq!(other.diff(&state), vec![StateDiff::Reg(3, -5, 1), StateDiff::Mem(0x20, 0, 9)]);
+
+        // 导出文件的内存部分与 data.txt 格式相同
+        let args: Vec<String> = ["tomasulo", "--dump", "traces/test_state_dump.txt"].iter().map(|arg| arg.to_string()).collect();
+        state.dump(&args).unwrap();
+        assert!(state.dump(&args[..2]).is_err());
+        let mut trace = Trace::new("traces/test_state_load.txt");
+        let mut loaded = SingleCycleCpu::new(&mut trace);
+        Parser::new().read_data(&mut loaded, "traces/test_state_dump.txt").unwrap();
+        assert_eq!(loaded.state().memory, state.memory);
+    }
+
+    fn checkpoint_cpu(trace: &mut Trace) -> TomasuloCpu<'_> {
+        let mut cpu = TomasuloCpu::new(trace);
+        cpu.set_rename_mode(RenameMode::PhysRegFile(40));
+        cpu.set_select_policy(SelectPolicy::Random(3));
+        let mut cache = CacheHierarchy::new("64,2,16,random:5,1".parse().unwrap(), None, 10).unwrap();
+        cache.set_mshrs(Some(2)).unwrap();
+        cpu.set_cache(Some(cache));
+        cpu.write_memory(0x0, 7);
+        cpu.write_memory(0x4, 3);
+        for inst in mixed_program().into_iter().chain(store_program()) {
+            cpu.add_inst(inst);
+        }
+        cpu
+    }
+
+    #[test]
+    fn checkpoint_restore_continues_identically() {
+        let mut trace = Trace::new("traces/test_checkpoint_full.txt");
+        let mut full = checkpoint_cpu(&mut trace);
+        let full_stats = full.run();
+
+        let mut trace = Trace::new("traces/test_checkpoint.txt");
+        let mut cpu = checkpoint_cpu(&mut trace);
+        while cpu.cycles() < full_stats.cycles / 2 {
+            cpu.step();
+        }
+        let checkpoint = cpu.checkpoint();
+        // 从检查点恢复到一个新的 CPU，配置与程序都来自检查点
+        let mut trace = Trace::new("traces/test_checkpoint_restored.txt");
+        let mut restored = TomasuloCpu::new(&mut trace);
+        restored.restore(&checkpoint).unwrap();
+        assert_eq!(restored.cycles(), full_stats.cycles / 2);
+        let stats = restored.run();
+        // 派生指标依赖的累加量随检查点单独保存，但不出现在统计信息中
+        assert_eq!(stats.to_json(), full_stats.to_json());
+        assert!(!stats.to_json().contains("rob_occupancy_sum"));
+        assert_eq!(restored.state(), full.state());
+        assert!(restored.restore("{}").is_err());
+
+        let mut trace = Trace::new("traces/test_checkpoint_single.txt");
+        let mut single = SingleCycleCpu::new(&mut trace);
+        single.write_memory(0x0, 7);
+        for inst in store_program() {
+            single.add_inst(inst);
+        }
+        for _ in 0..3 {
+            single.step();
+        }
+        let checkpoint = single.checkpoint();
+        let full_stats = single.run();
+        let mut trace = Trace::new("traces/test_checkpoint_single_restored.txt");
+        let mut restored = SingleCycleCpu::new(&mut trace);
+        restored.restore(&checkpoint).unwrap();
+        assert_eq!(restored.pc(), 3);
+        assert_eq!(restored.run().to_json(), full_stats.to_json());
+        assert_eq!(restored.state(), single.state());
+    }
+
+    fn sampling_program() -> Vec<Instruction> {
+        (0..40).flat_map(|i| [
+            Instruction::Ld(1, 0, (i % 8) * 4, Width::Word),
+            Instruction::Mul(Operand::new(2, 1, 3)),
+            Instruction::Add(Operand::new(3, 2, 1)),
+            Instruction::Sd(3, 0, 0x40 + (i % 4) * 4, StoreWidth::Word),
+            Instruction::Div(Operand::new(4, 3, 1)),
+        ]).collect()
+    }
+
+    fn sampling_functional(trace: &mut Trace) -> SingleCycleCpu<'_> {
+        let mut cpu = SingleCycleCpu::new(trace);
+        for addr in 0..8 {
+            cpu.write_memory(addr * 4, addr as i32 + 1);
+        }
+        for inst in sampling_program() {
+            cpu.add_inst(inst);
+        }
+        cpu
+    }
+
+    #[test]
+    fn sampling_estimates_ipc() {
+        assert!("10,5".parse::<Sampling>().is_err());
+        assert!("0,50,100,120".parse::<Sampling>().is_err());
+        let total = sampling_program().len();
+
+        // 不快进、不预热且详细模拟全部指令时与完整的详细模拟相同，
+        let mut trace = Trace::new("traces/test_sampling_full.txt");
+        let mut full = TomasuloCpu::new(&mut trace);
+        for addr in 0..8 {
+            full.write_memory(addr * 4, addr as i32 + 1);
+        }
+        for inst in sampling_program() {
+            full.add_inst(inst);
+        }
+        let full_stats = full.run();
+        let mut trace = Trace::new("traces/test_sampling_functional.txt");
+        let mut functional = sampling_functional(&mut trace);
+        let mut trace = Trace::new("traces/test_sampling.txt");
+        let stats = Sampling{ fast_forward: 0, warmup: 0, detail: total, period: None }.run(&mut functional, &mut trace, |_| {});
+        // 完整运行在最后一条指令提交的下一周期才确认结束
+        assert_eq!(stats.samples, vec![(total, full_stats.cycles - 1)]);
+        assert!(stats.confidence_interval.is_none());
+
+        // 周期性采样：快进与详细模拟的指令数之和为总指令数，最终状态与完整运行相同
+        let mut trace = Trace::new("traces/test_sampling_functional.txt");
+        let mut functional = sampling_functional(&mut trace);
+        let mut trace = Trace::new("traces/test_sampling.txt");
+        let sampling: Sampling = "15,10,20,40".parse().unwrap();
+        let stats = sampling.run(&mut functional, &mut trace, |cpu| cpu.set_rename_mode(RenameMode::PhysRegFile(40)));
+        assert_eq!(stats.samples.len(), 4);
+        assert!(stats.samples.iter().all(|(insts, _)| *insts >= 20));
+        assert_eq!(stats.fast_forwarded + stats.detailed, total);
+        assert_eq!(stats.detailed, 4 * 30);
+        assert_eq!(functional.state(), full.state());
+        let (low, high) = stats.confidence_interval.unwrap();
+        assert!(low <= stats.mean_ipc && stats.mean_ipc <= high);
+        assert!((stats.mean_ipc - full_stats.ipc).abs() < 0.2 * full_stats.ipc);
+    }
+
+    #[test]
+    fn initial_state_modes() {
+        assert_eq!("random".parse::<InitMode>().unwrap(), InitMode::Random(None));
+        assert_eq!("random:7".parse::<InitMode>().unwrap(), InitMode::Random(Some(7)));
+        assert_eq!("zero".parse::<InitMode>().unwrap(), InitMode::Zero);
+        assert!("random:x".parse::<InitMode>().is_err());
+        let args = |args: &[&str]| InitMode::from_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>());
+        assert_eq!(args(&["tomasulo", "--init", "zero"]), Ok(InitMode::Zero));
+        assert_eq!(args(&["tomasulo"]), Ok(InitMode::Random(None)));
+        assert!(args(&["tomasulo", "--init"]).is_err());
+        let parser = Parser::new();
+
+        // 没有列出的寄存器为 0，内存部分可选
+        std::fs::write("traces/test_init_state.txt", "# registers\nR1: 5\nR2: -3\n# memory\n16: 9\n").unwrap();
+        let init: InitMode = "traces/test_init_state.txt".parse().unwrap();
+        let mut trace = Trace::new("traces/test_init.txt");
+        let mut cpu = TomasuloCpu::new(&mut trace);
+        cpu.set_rename_mode(RenameMode::PhysRegFile(40));
+        cpu.set_regs(3, 100);
+        parser.init(&mut cpu, &init).unwrap();
+        cpu.add_inst(Instruction::Add(Operand::new(4, 1, 2)));
+        cpu.add_inst(Instruction::Ld(5, 0, 16, Width::Word));
+        cpu.run();
+        assert_eq!(cpu.regs()[1..6], [5, -3, 0, 2, 9]);
+
+        // 指定种子的随机初始化可以复现
+        let random_regs = |mode: &InitMode| {
+            let mut trace = Trace::new("traces/test_init_single.txt");
+            let mut cpu = SingleCycleCpu::new(&mut trace);
+            parser.init(&mut cpu, mode).unwrap();
+            *cpu.regs()
+        };
+        let regs = random_regs(&InitMode::Random(Some(7)));
+        assert_eq!(regs, random_regs(&InitMode::Random(Some(7))));
+        assert!(regs.iter().all(|reg| (0..1000).contains(reg)));
+        assert_ne!(regs, random_regs(&InitMode::Random(Some(8))));
+        assert_eq!(random_regs(&InitMode::Zero), [0; 32]);
+
+        // 初始状态文件中的内存同样要在内存映射中
+        std::fs::write("traces/test_init_state.txt", "R1: 5\n2097152: 1\n").unwrap();
+        let mut trace = Trace::new("traces/test_init_single.txt");
+        let mut cpu = SingleCycleCpu::new(&mut trace);
+        cpu.set_memory_map(Some(MemoryMap::standard()));
+        assert!(parser.init(&mut cpu, &init).is_err());
+    }
+}
 
@@ -1,12 +1,66 @@
 use regex::Regex;
 use std::fs::File;
 use std::io::Read;
+use std::str::FromStr;
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
 
-use super::cpu::{ Instruction, Operand, Cpu };
+use super::cpu::{ Instruction, Operand, Cpu, Width, StoreWidth, ArchState };
+
+/// 寄存器的初始化方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitMode {
+    /// 随机取 [0, 1000) 中的值，参数为随机数种子，None 时每次运行都不同
+    Random(Option<u64>),
+    /// 全部为 0
+    Zero,
+    /// 从初始状态文件中读取寄存器以及可选的内存，格式与导出的状态文件相同
+    File(String)
+}
+
+impl Default for InitMode {
+    fn default() -> Self {
+        InitMode::Random(None)
+    }
+}
+
+impl InitMode {
+    /// 从命令行参数中读取 `--init mode`，没有该参数时为默认的随机初始化
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        match args.iter().position(|arg| arg == "--init") {
+            Some(pos) => args.get(pos + 1).ok_or("missing init mode".to_string())?.parse(),
+            None => Ok(InitMode::default())
+        }
+    }
+}
+
+impl FromStr for InitMode {
+    type Err = String;
+
+    /// 解析 `random[:seed]`、`zero` 或者初始状态文件名
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(InitMode::Random(None)),
+            "zero" => Ok(InitMode::Zero),
+            _ => match s.strip_prefix("random:") {
+                Some(seed) => seed.parse()
+                    .map(|seed| InitMode::Random(Some(seed)))
+                    .map_err(|_| format!("invalid seed: {}", seed)),
+                None => Ok(InitMode::File(s.to_string()))
+            }
+        }
+    }
+}
 
 
 pub struct Parser ();
 
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Parser {
     pub const fn new() -> Self {
         Self()
@@ -16,14 +70,38 @@ impl Parser {
         where S: Into<String>
     {
         let inst : String = inst.into();
-        match inst.chars().nth(0) {
+        match inst.chars().next() {
             Some('L') => {
-                let pattern = Regex::new(r"LD,R([0-9]*),R([0-9]*),([0-9]*)").unwrap();
+                let pattern = Regex::new(r"(LD|LW|LBU|LB|LHU|LH),R([0-9]*),R([0-9]*),([0-9]*)").unwrap();
                 let cap = pattern.captures(&inst).unwrap();
+                let width = match &cap[1] {
+                    "LB" => Width::Byte,
+                    "LBU" => Width::ByteUnsigned,
+                    "LH" => Width::Half,
+                    "LHU" => Width::HalfUnsigned,
+                    _ => Width::Word
+                };
                 Instruction::Ld(
-                    usize::from_str_radix(&cap[1], 10).unwrap(), 
-                    usize::from_str_radix(&cap[2], 10).unwrap(),
-                    u32::from_str_radix(&cap[3], 10).unwrap(), 
+                    cap[2].parse::<usize>().unwrap(), 
+                    cap[3].parse::<usize>().unwrap(),
+                    cap[4].parse::<u32>().unwrap(), 
+                    width
+                )
+            },
+
+            Some('S') if !inst.starts_with("SUB") => {
+                let pattern = Regex::new(r"(SD|SW|SB|SH),R([0-9]*),R([0-9]*),([0-9]*)").unwrap();
+                let cap = pattern.captures(&inst).unwrap();
+                let width = match &cap[1] {
+                    "SB" => StoreWidth::Byte,
+                    "SH" => StoreWidth::Half,
+                    _ => StoreWidth::Word
+                };
+                Instruction::Sd(
+                    cap[2].parse::<usize>().unwrap(), 
+                    cap[3].parse::<usize>().unwrap(),
+                    cap[4].parse::<u32>().unwrap(), 
+                    width
                 )
             },
 
@@ -32,9 +110,9 @@ impl Parser {
                 let cap = pattern.captures(&inst).unwrap();
                 Instruction::Add(
                     Operand::new(
-                        usize::from_str_radix(&cap[1], 10).unwrap(),
-                        usize::from_str_radix(&cap[2], 10).unwrap(), 
-                        usize::from_str_radix(&cap[3], 10).unwrap()
+                        cap[1].parse::<usize>().unwrap(),
+                        cap[2].parse::<usize>().unwrap(), 
+                        cap[3].parse::<usize>().unwrap()
                     )
                 )
             },
@@ -44,9 +122,9 @@ impl Parser {
                 let cap = pattern.captures(&inst).unwrap();
                 Instruction::Sub(
                     Operand::new(
-                        usize::from_str_radix(&cap[1], 10).unwrap(),
-                        usize::from_str_radix(&cap[2], 10).unwrap(), 
-                        usize::from_str_radix(&cap[3], 10).unwrap()
+                        cap[1].parse::<usize>().unwrap(),
+                        cap[2].parse::<usize>().unwrap(), 
+                        cap[3].parse::<usize>().unwrap()
                     )
                 )
             },
@@ -56,9 +134,9 @@ impl Parser {
                 let cap = pattern.captures(&inst).unwrap();
                 Instruction::Mul(
                     Operand::new(
-                        usize::from_str_radix(&cap[1], 10).unwrap(),
-                        usize::from_str_radix(&cap[2], 10).unwrap(), 
-                        usize::from_str_radix(&cap[3], 10).unwrap()
+                        cap[1].parse::<usize>().unwrap(),
+                        cap[2].parse::<usize>().unwrap(), 
+                        cap[3].parse::<usize>().unwrap()
                     )
                 )
             }
@@ -68,9 +146,9 @@ impl Parser {
                 let cap = pattern.captures(&inst).unwrap();
                 Instruction::Div(
                     Operand::new(
-                        usize::from_str_radix(&cap[1], 10).unwrap(),
-                        usize::from_str_radix(&cap[2], 10).unwrap(), 
-                        usize::from_str_radix(&cap[3], 10).unwrap()
+                        cap[1].parse::<usize>().unwrap(),
+                        cap[2].parse::<usize>().unwrap(), 
+                        cap[3].parse::<usize>().unwrap()
                     )
                 )
             },
@@ -80,14 +158,14 @@ impl Parser {
                 let cap = pattern.captures(&inst).unwrap();
                 // Instruction::Div(
                 //     Operand::new(
-                //         usize::from_str_radix(&cap[1], 10).unwrap(),
-                //         usize::from_str_radix(&cap[2], 10).unwrap(), 
-                //         usize::from_str_radix(&cap[3], 10).unwrap()
+                //         cap[1].parse::<usize>().unwrap(),
+                //         cap[2].parse::<usize>().unwrap(), 
+                //         cap[3].parse::<usize>().unwrap()
                 //     )
                 // )
                 Instruction::Jump(
-                    usize::from_str_radix(&cap[1], 10).unwrap(),
-                    usize::from_str_radix(&cap[2], 10).unwrap()
+                    cap[1].parse::<usize>().unwrap(),
+                    cap[2].parse::<usize>().unwrap()
                 )
             }
 
@@ -100,29 +178,92 @@ impl Parser {
     pub fn read_inst<C, S>(&self, cpu: &mut C, filename: S) -> Result<(), String>
         where C: Cpu, S: Into<String> 
     {
-        let mut file = File::open(filename.into()).map_err( |err| { format!("err: {}", err) })?;
-        let mut insts: String = String::new();
-        file.read_to_string(&mut insts).map_err(|err| { format!("err: {}", err) })?;
-        for inst in insts.lines() {
-            let inst = self.parse(inst);
+        for inst in self.read_program(filename)? {
             cpu.add_inst(inst);
         }
         Ok(())
     }
 
+    /// 读取指令文件，返回解析后的程序
+    pub fn read_program<S>(&self, filename: S) -> Result<Vec<Instruction>, String>
+        where S: Into<String>
+    {
+        let mut file = File::open(filename.into()).map_err( |err| { format!("err: {}", err) })?;
+        let mut insts: String = String::new();
+        file.read_to_string(&mut insts).map_err(|err| { format!("err: {}", err) })?;
+        Ok(insts.lines().map(|inst| self.parse(inst)).collect())
+    }
+
     pub fn read_data<C, S>(&self, cpu: &mut C, filename: S) -> Result<(), String>
         where C: Cpu, S: Into<String> 
     {
         let mut file = File::open(filename.into()).map_err( |err| { format!("err: {}", err) })?;
         let mut datas: String = String::new();
         file.read_to_string(&mut datas).map_err(|err| { format!("err: {}", err) })?;
-        for data in datas.lines() {
-            let pattern = Regex::new(r"([0-9]*): ([0-9]*)").unwrap();
-            let cap = pattern.captures(&data).unwrap();
-            let addr = u32::from_str_radix(&cap[1], 10).unwrap();
-            let val = i32::from_str_radix(&cap[2], 10).unwrap();
-            cpu.write_memory(addr, val);      
+        // 与导出的状态文件中的内存部分格式相同，跳过空行、`#` 开头的注释以及寄存器，
+        // 因此导出的状态文件也可以直接作为数据文件读入
+        let pattern = Regex::new(r"^([0-9]+):\s*(-?[0-9]+)$").unwrap();
+        let skip = |line: &&str| line.is_empty() || line.starts_with('#') || line.starts_with('R');
+        for data in datas.lines().map(str::trim).filter(|line| !skip(line)) {
+            let cap = pattern.captures(data).ok_or(format!("err: invalid data: {}", data))?;
+            let addr = cap[1].parse::<u32>().unwrap();
+            let val = cap[2].parse::<i32>().unwrap();
+            Self::load_word(cpu, addr, val)?;
+        }
+        Ok(())
+    }
+
+    /// 写入一个初始化的字，初始化数据由加载器写入，只要求地址在某个区域中，不检查写权限
+    fn load_word<C: Cpu>(cpu: &mut C, addr: u32, val: i32) -> Result<(), String> {
+        if cpu.memory_map().is_some_and(|map| map.find(addr, 4).is_none()) {
+            return Err(format!("err: data address {:#x} is not mapped", addr))
         }
+        cpu.write_memory(addr, val);
         Ok(())
     }
+
+    /// 按初始化方式设置寄存器，初始状态文件中的内存同时写入
+    pub fn init<C: Cpu>(&self, cpu: &mut C, mode: &InitMode) -> Result<(), String> {
+        match mode {
+            InitMode::Random(seed) => {
+                let mut rng = match seed {
+                    Some(seed) => ChaCha12Rng::seed_from_u64(*seed),
+                    None => ChaCha12Rng::from_entropy()
+                };
+                for index in 0..32 {
+                    cpu.set_regs(index, rng.gen_range(0..1000));
+                }
+            },
+            InitMode::Zero => {
+                for index in 0..32 {
+                    cpu.set_regs(index, 0);
+                }
+            },
+            InitMode::File(filename) => {
+                let state = self.read_state(filename.as_str())?;
+                for (index, val) in state.regs.iter().enumerate() {
+                    cpu.set_regs(index, *val);
+                }
+                for (addr, val) in state.memory {
+                    Self::load_word(cpu, addr, val)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 读取导出的状态文件，`.json` 结尾的文件为 JSON 格式，否则为文本格式
+    pub fn read_state<S>(&self, filename: S) -> Result<ArchState, String>
+        where S: Into<String>
+    {
+        let filename: String = filename.into();
+        let mut file = File::open(&filename).map_err( |err| { format!("err: {}", err) })?;
+        let mut state = String::new();
+        file.read_to_string(&mut state).map_err(|err| { format!("err: {}", err) })?;
+        if filename.ends_with(".json") {
+            ArchState::from_json(&state)
+        }else {
+            state.parse()
+        }
+    }
 }